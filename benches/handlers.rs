@@ -0,0 +1,168 @@
+// Copyright (c) 2025, TheByteSlayer, Triangular
+// Stores structured Data in JSON Files and makes it accessible over TCP, written in Rust.
+
+use criterion::{BenchmarkId, Criterion, criterion_group};
+use std::fs;
+use std::hint::black_box;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use triangular_database::configuration::{self, Config};
+use triangular_database::response::Response;
+use triangular_database::tree;
+
+/// Module counts of the containers every handler is measured against.
+const SIZES: &[usize] = &[10, 1_000, 100_000];
+
+static NEXT_MODULE: AtomicUsize = AtomicUsize::new(0);
+
+fn data_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("triangular-bench-{}", std::process::id()))
+}
+
+/// Writes the bench configuration file into the data directory.
+fn write_config(data_dir: &Path, compress_storage: bool) -> PathBuf {
+    let config = Config {
+        silent: true,
+        expiry_sweep_interval_secs: 0,
+        data_dir: data_dir.to_string_lossy().into_owned(),
+        compress_storage,
+        ..Config::default()
+    };
+    
+    let config_path = data_dir.join(configuration::DEFAULT_CONFIG_PATH);
+    fs::write(&config_path, toml::to_string(&config).unwrap()).expect("failed to write the bench configuration");
+    config_path
+}
+
+/// Points the handlers at a fresh data directory so real data is never touched.
+/// The configuration is read from a file there, so RECONFIG can switch settings.
+fn set_up_data_dir() -> PathBuf {
+    let data_dir = data_dir();
+    fs::create_dir_all(&data_dir).expect("failed to create the bench data directory");
+    
+    let config_path = write_config(&data_dir, false);
+    let config = Config::load_or_create_from(&config_path.to_string_lossy()).expect("failed to load the bench configuration");
+    configuration::set_config(config);
+    tree::initialize_tree().expect("failed to create the bench data directory");
+    
+    data_dir
+}
+
+/// Switches `compress_storage` the way RECONFIG does; containers move to the new
+/// format on their next write.
+fn set_compress_storage(compress_storage: bool) {
+    write_config(&data_dir(), compress_storage);
+    configuration::reload_config().expect("failed to reload the bench configuration");
+}
+
+/// Modules with ids `m0` to `m<size - 1>`.
+fn modules_with(size: usize) -> Vec<serde_json::Value> {
+    (0..size)
+        .map(|i| serde_json::json!({ "id": format!("m{}", i), "value": "x" }))
+        .collect()
+}
+
+/// Imports a container `<prefix>_<size>` of `size` modules with ids `m0` to `m<size - 1>`.
+fn container_with(prefix: &str, size: usize) -> String {
+    let name = format!("{}_{}", prefix, size);
+    let modules = modules_with(size);
+    
+    let response = tree::handle_import(&name, &serde_json::to_string(&modules).unwrap());
+    assert!(matches!(response, Response::Ok(_)), "import failed: {}", response.to_wire());
+    
+    name
+}
+
+fn handlers(c: &mut Criterion) {
+    for &size in SIZES {
+        let container = container_with("bench", size);
+        let module = format!("m{}", size / 2);
+        
+        let mut group = c.benchmark_group(format!("{} modules", size));
+        if size >= 100_000 {
+            group.sample_size(10);
+        }
+        
+        group.bench_function(BenchmarkId::new("GET", size), |b| {
+            b.iter(|| tree::handle_get(black_box(&container), black_box(&module), "value"))
+        });
+        
+        group.bench_function(BenchmarkId::new("SET", size), |b| {
+            b.iter(|| tree::handle_set(black_box(&container), black_box(&module), "value", "y"))
+        });
+        
+        group.bench_function(BenchmarkId::new("INIT", size), |b| {
+            b.iter(|| {
+                let id = format!("new{}", NEXT_MODULE.fetch_add(1, Ordering::Relaxed));
+                tree::handle_init(black_box(&container), &id, &[], None)
+            })
+        });
+        
+        group.finish();
+    }
+}
+
+/// Cost of the module index alone, without reading or writing the container.
+fn lookups(c: &mut Criterion) {
+    let manager = tree::get_container_manager();
+    
+    for &size in SIZES {
+        let container = format!("lookup_{}", size);
+        let modules = modules_with(size);
+        let module = format!("m{}", size / 2);
+        
+        let mut group = c.benchmark_group(format!("{} modules", size));
+        
+        group.bench_function(BenchmarkId::new("lookup hit", size), |b| {
+            b.iter(|| manager.module_position(&container, black_box(&modules), "id", black_box(&module)))
+        });
+        
+        group.bench_function(BenchmarkId::new("lookup miss", size), |b| {
+            b.iter(|| manager.module_position(&container, black_box(&modules), "id", black_box("absent")))
+        });
+        
+        group.finish();
+    }
+}
+
+/// CPU cost of `compress_storage` against the space it saves. Criterion only
+/// measures time, so the size of each container on disk is printed alongside.
+fn storage(c: &mut Criterion) {
+    for compress_storage in [false, true] {
+        set_compress_storage(compress_storage);
+        let label = if compress_storage { "gzip on" } else { "gzip off" };
+        
+        for &size in SIZES {
+            let container = container_with(if compress_storage { "gzip" } else { "plain" }, size);
+            let module = format!("m{}", size / 2);
+            
+            println!("{} modules, {}: {} bytes on disk", size, label, tree::handle_size(Some(&container)).to_wire());
+            
+            let mut group = c.benchmark_group(format!("{} modules", size));
+            if size >= 100_000 {
+                group.sample_size(10);
+            }
+            
+            group.bench_function(BenchmarkId::new(format!("GET {}", label), size), |b| {
+                b.iter(|| tree::handle_get(black_box(&container), black_box(&module), "value"))
+            });
+            
+            group.bench_function(BenchmarkId::new(format!("SET {}", label), size), |b| {
+                b.iter(|| tree::handle_set(black_box(&container), black_box(&module), "value", "y"))
+            });
+            
+            group.finish();
+        }
+    }
+}
+
+criterion_group!(benches, handlers, lookups, storage);
+
+fn main() {
+    let data_dir = set_up_data_dir();
+    
+    benches();
+    Criterion::default().configure_from_args().final_summary();
+    
+    let _ = fs::remove_dir_all(data_dir);
+}
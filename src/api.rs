@@ -1,18 +1,339 @@
 // Copyright (c) 2025, TheByteSlayer, Triangular
 // Stores structured Data in JSON Files and makes it accessible over TCP, written in Rust.
 
-use std::sync::OnceLock;
+use std::sync::{Arc, Mutex, OnceLock, RwLock, RwLockWriteGuard};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::mpsc;
-use std::net::{TcpListener, TcpStream};
-use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
 use std::thread;
-use crate::configuration::Config;
+use std::time::{Duration, Instant};
+use crate::audit::{self, AuditEntry};
+use crate::jsonrpc;
+use crate::metrics;
+use crate::configuration::{self, Config, RequestDelimiter};
+use crate::response::{ErrorCode, Response};
 use crate::tree;
+use socket2::{Domain, Protocol, SockRef, Socket, Type};
 
 static API_MANAGER: OnceLock<ApiManager> = OnceLock::new();
 
+/// A command the server understands, with the syntax shown by HELP.
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub syntax: &'static str,
+    pub summary: &'static str,
+}
+
+/// Every command the server understands. HELP, metrics and command suggestions
+/// are all driven from this list, so new commands belong here as well as in `dispatch`.
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "PING",
+        syntax: "PING [message]",
+        summary: "Replies PONG, or echoes the message.",
+    },
+    CommandSpec {
+        name: "VERSION",
+        syntax: "VERSION",
+        summary: "Returns the server and protocol version.",
+    },
+    CommandSpec {
+        name: "INIT",
+        syntax: "INIT <container> <id> [TTL <seconds>] [IDEM <token>] [key=value ...]",
+        summary: "Creates a module from the container template.",
+    },
+    CommandSpec {
+        name: "INITJSON",
+        syntax: "INITJSON <container> <json object>",
+        summary: "Creates a module from a JSON object that carries its own id.",
+    },
+    CommandSpec {
+        name: "BULKINIT",
+        syntax: "BULKINIT <container> <json array>",
+        summary: "Creates many modules at once, skipping and listing ids that already exist.",
+    },
+    CommandSpec {
+        name: "SET",
+        syntax: "SET <container> <module> <key> <value>",
+        summary: "Sets a key on a module.",
+    },
+    CommandSpec {
+        name: "SETM",
+        syntax: "SETM <container> <module> [PARTIAL] <key> <value> [<key> <value> ...]",
+        summary: "Sets several values of a module at once; PARTIAL applies the valid ones and reports each key.",
+    },
+    CommandSpec {
+        name: "SETNX",
+        syntax: "SETNX <container> <module> <key> <value>",
+        summary: "Sets a key only if the module does not have it yet.",
+    },
+    CommandSpec {
+        name: "CAS",
+        syntax: "CAS <container> <module> <key> <expected> <new>",
+        summary: "Replaces a value only if it currently equals the expected one.",
+    },
+    CommandSpec {
+        name: "SETJSON",
+        syntax: "SETJSON <container> <module> <key> <json value>",
+        summary: "Sets a key to a JSON value, rejecting malformed JSON instead of storing it as text.",
+    },
+    CommandSpec {
+        name: "SETBIN",
+        syntax: "SETBIN <container> <module> <key> <base64>",
+        summary: "Stores a binary value sent as base64.",
+    },
+    CommandSpec {
+        name: "DECR",
+        syntax: "DECR <container> <module> <key> [amount]",
+        summary: "Decrements a numeric value by the amount, 1 by default.",
+    },
+    CommandSpec {
+        name: "EXPIRE",
+        syntax: "EXPIRE <container> <module> <seconds>",
+        summary: "Makes a module expire the given number of seconds from now.",
+    },
+    CommandSpec {
+        name: "PERSIST",
+        syntax: "PERSIST <container> <module>",
+        summary: "Removes the expiry of a module.",
+    },
+    CommandSpec {
+        name: "GET",
+        syntax: "GET <container> <module> <key>",
+        summary: "Returns one value of a module; objects and arrays as JSON.",
+    },
+    CommandSpec {
+        name: "MGET",
+        syntax: "MGET <container> <module> <key> [key ...]",
+        summary: "Lists several values of a module, in the order of the keys.",
+    },
+    CommandSpec {
+        name: "GETALL",
+        syntax: "GETALL <container> <module>",
+        summary: "Returns a whole module as JSON.",
+    },
+    CommandSpec {
+        name: "MGETALL",
+        syntax: "MGETALL <container> <module> [module ...]",
+        summary: "Returns several whole modules as JSON, one per line; (nil) for missing ones.",
+    },
+    CommandSpec {
+        name: "VER",
+        syntax: "VER <container> <module>",
+        summary: "Returns the version of a module, which every write to it increases.",
+    },
+    CommandSpec {
+        name: "CONTAINERS",
+        syntax: "CONTAINERS",
+        summary: "Lists the names of all containers.",
+    },
+    CommandSpec {
+        name: "SIZE",
+        syntax: "SIZE [container]",
+        summary: "Reports the bytes a container, or all containers and tree.json, take on disk.",
+    },
+    CommandSpec {
+        name: "LIST",
+        syntax: "LIST <container> [module [WITHVERSION] | PREFIX <p> | LIMIT <n> | OFFSET <n> | SORT <key> [ASC|DESC]]",
+        summary: "Lists module ids, or the keys of one module; WITHVERSION adds _version.",
+    },
+    CommandSpec {
+        name: "FIND",
+        syntax: "FIND <container> <key> <eq|ne|gt|lt|gte|lte> <value>",
+        summary: "Lists the ids of modules whose key satisfies the comparison.",
+    },
+    CommandSpec {
+        name: "PLUCK",
+        syntax: "PLUCK <container> <key>",
+        summary: "Lists one key of every module that has it, as id=value items.",
+    },
+    CommandSpec {
+        name: "DUMP",
+        syntax: "DUMP [container]",
+        summary: "Returns one container, or all of them, as JSON.",
+    },
+    CommandSpec {
+        name: "IMPORT",
+        syntax: "IMPORT <container> <json array>",
+        summary: "Replaces the modules of a container.",
+    },
+    CommandSpec {
+        name: "REPAIR",
+        syntax: "REPAIR <container>",
+        summary: "Wraps a container file holding a single object into an array.",
+    },
+    CommandSpec {
+        name: "COMPACT",
+        syntax: "COMPACT <container>",
+        summary: "Rewrites a container file without expired modules and reports its size before and after.",
+    },
+    CommandSpec {
+        name: "VERIFY",
+        syntax: "VERIFY",
+        summary: "Checks every container file and lists the corrupt ones.",
+    },
+    CommandSpec {
+        name: "MOVE",
+        syntax: "MOVE <source> <module> <target>",
+        summary: "Moves a module to another container.",
+    },
+    CommandSpec {
+        name: "RELOAD",
+        syntax: "RELOAD",
+        summary: "Re-reads tree.json and creates missing container files.",
+    },
+    CommandSpec {
+        name: "DRYRUN",
+        syntax: "DRYRUN <command> [argument ...]",
+        summary: "Checks a mutating command without writing anything.",
+    },
+    CommandSpec {
+        name: "CMETA",
+        syntax: "CMETA GET|SET <container> <key> [value]",
+        summary: "Reads or writes container metadata.",
+    },
+    CommandSpec {
+        name: "STATS",
+        syntax: "STATS",
+        summary: "Returns request and connection counters.",
+    },
+    CommandSpec {
+        name: "METRICS",
+        syntax: "METRICS",
+        summary: "Returns the counters in Prometheus text format.",
+    },
+    CommandSpec {
+        name: "RECONFIG",
+        syntax: "RECONFIG",
+        summary: "Re-reads the configuration file and applies runtime settings.",
+    },
+    CommandSpec {
+        name: "SUBSCRIBE",
+        syntax: "SUBSCRIBE <container>",
+        summary: "Streams EVENT lines for changes made by other connections. They arrive between replies, so after HELLO 4 they cannot be mistaken for one.",
+    },
+    CommandSpec {
+        name: "UNSUBSCRIBE",
+        syntax: "UNSUBSCRIBE [container]",
+        summary: "Stops change notifications for one or all containers.",
+    },
+    CommandSpec {
+        name: "LOCK",
+        syntax: "LOCK <name> [timeout ms]",
+        summary: "Waits for and takes an advisory lock, held until UNLOCK or disconnect.",
+    },
+    CommandSpec {
+        name: "UNLOCK",
+        syntax: "UNLOCK <name>",
+        summary: "Releases an advisory lock held by this connection.",
+    },
+    CommandSpec {
+        name: "WATCH",
+        syntax: "WATCH <container> <module>",
+        summary: "Makes the next EXEC abort if the module changes before it runs.",
+    },
+    CommandSpec {
+        name: "UNWATCH",
+        syntax: "UNWATCH",
+        summary: "Forgets all watched modules.",
+    },
+    CommandSpec {
+        name: "MULTI",
+        syntax: "MULTI",
+        summary: "Starts queueing commands for EXEC.",
+    },
+    CommandSpec {
+        name: "EXEC",
+        syntax: "EXEC",
+        summary: "Runs the queued commands atomically, replying EXEC <count> and then, per command, a line with the reply's length in bytes followed by the reply.",
+    },
+    CommandSpec {
+        name: "DISCARD",
+        syntax: "DISCARD",
+        summary: "Drops the queued commands and watched modules.",
+    },
+    CommandSpec {
+        name: "COMPRESS",
+        syntax: "COMPRESS ON|OFF",
+        summary: "Gzips large framed replies on this connection, marked by a GZIP line before the chunks; requires HELLO 4.",
+    },
+    CommandSpec {
+        name: "JSONRPC",
+        syntax: "JSONRPC",
+        summary: "Switches the connection to JSON-RPC 2.0, one request object or batch per line.",
+    },
+    CommandSpec {
+        name: "HELLO",
+        syntax: "HELLO <version>",
+        summary: "Negotiates the protocol version and lists the server capabilities. From version 4 on, replies are sent as <length> lines each followed by that many bytes and a newline, ended by a 0 line.",
+    },
+    CommandSpec {
+        name: "AUTH",
+        syntax: "AUTH <token>",
+        summary: "Authenticates the connection; the token's ACL entry decides which commands it may run.",
+    },
+    CommandSpec {
+        name: "HELP",
+        syntax: "HELP [command]",
+        summary: "Lists the commands, or describes one.",
+    },
+];
+
+/// Commands that change stored data and are recorded in the audit log.
+const MUTATING_COMMANDS: &[&str] = &["INIT", "INITJSON", "BULKINIT", "SET", "SETM", "SETNX", "CAS", "SETJSON", "SETBIN", "IMPORT", "DECR", "EXPIRE", "PERSIST", "REPAIR", "MOVE", "CMETA"];
+
+/// Version of the wire protocol, bumped whenever commands or responses change incompatibly.
+pub const PROTOCOL_VERSION: u32 = 4;
+
+/// Oldest protocol version HELLO accepts; version 1 errors carried no code.
+const MIN_PROTOCOL_VERSION: u32 = 2;
+
+/// First protocol version that sends lists one item per line instead of comma separated.
+const LINE_LISTS_VERSION: u32 = 3;
+
+/// First protocol version whose replies are framed, so a client can tell where
+/// one ends even when it spans lines or an EVENT follows right after it.
+pub const FRAMED_REPLIES_VERSION: u32 = 4;
+
+/// Commands handled by the session itself, which cannot be queued inside MULTI.
+const SESSION_COMMANDS: &[&str] = &["AUTH", "SUBSCRIBE", "UNSUBSCRIBE", "LOCK", "UNLOCK", "HELLO", "COMPRESS", "JSONRPC", "WATCH", "UNWATCH", "MULTI", "EXEC", "DISCARD"];
+
+/// Held shared by every command and exclusively by EXEC, so a transaction's
+/// WATCH check and its commands run without anything in between.
+static TRANSACTION_LOCK: RwLock<()> = RwLock::new(());
+
+/// How long LOCK waits when the client gives no timeout.
+const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Pause after the first failed accept; it doubles with every further failure in a row.
+const ACCEPT_BACKOFF_START: Duration = Duration::from_millis(10);
+const ACCEPT_BACKOFF_MAX: Duration = Duration::from_secs(1);
+
+/// Optional features announced in the HELLO reply.
+const CAPABILITIES: &[&str] = &["json", "subscriptions", "compression", "jsonrpc"];
+
+/// Announced in addition to `CAPABILITIES` while an ACL requires AUTH.
+const AUTH_CAPABILITY: &str = "auth";
+
+/// Commands a connection may run before AUTH when an ACL is configured.
+const PUBLIC_COMMANDS: &[&str] = &["AUTH", "HELLO", "PING", "JSONRPC"];
+
+/// Commands granted by the `@read` ACL entry; none of them change stored data.
+const READ_COMMANDS: &[&str] = &[
+    "PING", "VERSION", "GET", "MGET", "GETALL", "MGETALL", "VER", "CONTAINERS", "SIZE", "LIST", "FIND", "PLUCK", "DUMP",
+    "VERIFY", "STATS", "METRICS", "SUBSCRIBE", "UNSUBSCRIBE", "WATCH", "UNWATCH", "MULTI", "EXEC",
+    "DISCARD", "COMPRESS", "JSONRPC", "HELLO", "HELP",
+];
+
 struct ThreadPool {
     sender: mpsc::Sender<Job>,
+    submitted: AtomicUsize,
+    /// Jobs a worker has taken off the queue, shared with the workers.
+    started: Arc<AtomicUsize>,
+    /// Set once the queue reached `queue_depth_warning`, so the warning is logged
+    /// once per crossing rather than for every job.
+    above_warning: AtomicBool,
 }
 
 type Job = Box<dyn FnOnce() + Send + 'static>;
@@ -21,17 +342,25 @@ impl ThreadPool {
     fn new(size: usize) -> Self {
         let (sender, receiver) = mpsc::channel();
         let receiver = std::sync::Arc::new(std::sync::Mutex::new(receiver));
+        let started = Arc::new(AtomicUsize::new(0));
         
         // Spawn worker threads without storing their handles
         for _ in 0..size {
             let receiver = std::sync::Arc::clone(&receiver);
+            let started = Arc::clone(&started);
             thread::spawn(move || loop {
                 let job: Job = receiver.lock().unwrap().recv().unwrap();
+                started.fetch_add(1, Ordering::SeqCst);
                 job();
             });
         }
         
-        ThreadPool { sender }
+        ThreadPool {
+            sender,
+            submitted: AtomicUsize::new(0),
+            started,
+            above_warning: AtomicBool::new(false),
+        }
     }
     
     fn execute<F>(&self, f: F)
@@ -39,74 +368,780 @@ impl ThreadPool {
         F: FnOnce() + Send + 'static,
     {
         let job = Box::new(f);
+        self.submitted.fetch_add(1, Ordering::SeqCst);
         self.sender.send(job).unwrap();
+        
+        let config = configuration::get_config();
+        let depth = self.queue_depth();
+        if config.queue_depth_warning == 0 || depth < config.queue_depth_warning {
+            self.above_warning.store(false, Ordering::SeqCst);
+        } else if !self.above_warning.swap(true, Ordering::SeqCst) && !config.silent {
+            eprintln!("Warning: {} connections are waiting for a worker thread", depth);
+        }
+    }
+    
+    /// Jobs submitted that no worker has started yet.
+    fn queue_depth(&self) -> usize {
+        let started = self.started.load(Ordering::SeqCst);
+        self.submitted.load(Ordering::SeqCst).saturating_sub(started)
+    }
+}
+
+/// Token bucket allowing `rate` commands per second with bursts of up to one second's worth.
+struct RateLimiter {
+    rate: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    fn new(rate: u32) -> Self {
+        Self {
+            rate: rate as f64,
+            tokens: rate as f64,
+            last_refill: Instant::now(),
+        }
+    }
+    
+    fn try_acquire(&mut self) -> bool {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.last_refill = now;
+        
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
     }
 }
 
 pub struct ApiManager {
     thread_pool: ThreadPool,
+    active_connections: AtomicUsize,
+    next_connection_id: AtomicU64,
+}
+
+/// Per-connection state for commands whose effect outlives a single request.
+struct Session {
+    id: u64,
+    writer: SharedWriter,
+    /// Feeds the thread that forwards change notifications, created on first SUBSCRIBE.
+    events: Option<mpsc::Sender<String>>,
+    subscriptions: Vec<String>,
+    /// Version agreed on through HELLO, if the client sent one.
+    protocol_version: Option<u32>,
+    /// Modules from WATCH with the version they had at the time.
+    watched: Vec<(String, String, u64)>,
+    /// Commands waiting for EXEC, set between MULTI and EXEC or DISCARD.
+    queued: Option<Vec<String>>,
+    /// Set by COMPRESS ON to gzip responses above `compression_threshold_bytes`.
+    compress_responses: bool,
+    /// Set by JSONRPC; every later line is a JSON-RPC 2.0 request.
+    json_rpc: bool,
+    /// Token accepted by AUTH, looked up in the ACL on every command so RECONFIG
+    /// can revoke it.
+    token: Option<String>,
+}
+
+impl Session {
+    fn new(id: u64, writer: SharedWriter) -> Self {
+        Self {
+            id,
+            writer,
+            events: None,
+            subscriptions: Vec::new(),
+            protocol_version: None,
+            watched: Vec::new(),
+            queued: None,
+            compress_responses: false,
+            json_rpc: false,
+            token: None,
+        }
+    }
+    
+    /// Runs a request, handling connection-level commands here and passing the rest on.
+    fn process(&mut self, request: &str) -> Response {
+        let parts = split_arguments(request);
+        let command = parts.first().map(|command| command.to_uppercase()).unwrap_or_default();
+        
+        if !self.permits(&command) {
+            let response = Response::Error(ErrorCode::Forbidden, "Forbidden".to_string());
+            metrics::record(&command, &response);
+            return response;
+        }
+        
+        if self.queued.is_some() && !matches!(command.as_str(), "MULTI" | "EXEC" | "DISCARD" | "WATCH") {
+            return self.queue(&command, request);
+        }
+        
+        let response = match command.as_str() {
+            "WATCH" if parts.len() != 3 => Response::Error(ErrorCode::InvalidArguments, "WATCH requires container and module".to_string()),
+            "WATCH" => self.watch(parts[1], parts[2]),
+            "UNWATCH" => {
+                self.watched.clear();
+                Response::Ok("UNWATCH".to_string())
+            }
+            "MULTI" if self.queued.is_some() => Response::Error(ErrorCode::InvalidArguments, "MULTI cannot be nested".to_string()),
+            "MULTI" => {
+                self.queued = Some(Vec::new());
+                Response::Ok("MULTI".to_string())
+            }
+            "EXEC" => self.exec(),
+            "DISCARD" if self.queued.is_none() => Response::Error(ErrorCode::InvalidArguments, "DISCARD without MULTI".to_string()),
+            "DISCARD" => {
+                self.queued = None;
+                self.watched.clear();
+                Response::Ok("DISCARD".to_string())
+            }
+            "SUBSCRIBE" if parts.len() != 2 => Response::Error(ErrorCode::InvalidArguments, "SUBSCRIBE requires container".to_string()),
+            "SUBSCRIBE" => self.subscribe(parts[1]),
+            "UNSUBSCRIBE" if parts.len() > 2 => Response::Error(ErrorCode::InvalidArguments, "UNSUBSCRIBE takes 0 or 1 arguments".to_string()),
+            "UNSUBSCRIBE" => self.unsubscribe(parts.get(1).copied()),
+            "LOCK" if parts.len() < 2 || parts.len() > 3 => Response::Error(ErrorCode::InvalidArguments, "LOCK requires name and optional timeout".to_string()),
+            "LOCK" => match parts.get(2).map(|timeout| timeout.parse::<u64>().map(Duration::from_millis)) {
+                None => self.lock(parts[1], DEFAULT_LOCK_TIMEOUT),
+                Some(Ok(timeout)) => self.lock(parts[1], timeout),
+                Some(Err(_)) => Response::Error(ErrorCode::InvalidArguments, "LOCK timeout must be a number of milliseconds".to_string()),
+            },
+            "UNLOCK" if parts.len() != 2 => Response::Error(ErrorCode::InvalidArguments, "UNLOCK requires name".to_string()),
+            "UNLOCK" => {
+                if tree::get_container_manager().release_advisory_lock(parts[1], self.id) {
+                    Response::Ok(format!("UNLOCK {}", parts[1]))
+                } else {
+                    Response::Error(ErrorCode::NotLockOwner, "Lock not held by this connection".to_string())
+                }
+            }
+            "COMPRESS" => match parts.get(1).map(|mode| mode.to_uppercase()).as_deref() {
+                // Without framing a client could not tell a GZIP header from a reply starting with it
+                Some("ON") if parts.len() == 2 && !self.frames_replies() => {
+                    Response::Error(ErrorCode::UnsupportedProtocol, format!("COMPRESS requires protocol version {}", FRAMED_REPLIES_VERSION))
+                }
+                Some(mode @ ("ON" | "OFF")) if parts.len() == 2 => {
+                    self.compress_responses = mode == "ON";
+                    Response::Ok(format!("COMPRESS {}", mode))
+                }
+                _ => Response::Error(ErrorCode::InvalidArguments, "COMPRESS requires ON or OFF".to_string()),
+            },
+            "AUTH" if parts.len() != 2 => Response::Error(ErrorCode::InvalidArguments, "AUTH requires a token".to_string()),
+            "AUTH" => self.auth(parts[1]),
+            "JSONRPC" if parts.len() != 1 => Response::Error(ErrorCode::InvalidArguments, "JSONRPC takes no arguments".to_string()),
+            "JSONRPC" => {
+                self.json_rpc = true;
+                Response::Ok("JSONRPC".to_string())
+            }
+            "HELLO" => match parts.get(1).map(|version| version.parse::<u32>()) {
+                Some(Ok(version)) if parts.len() == 2 => self.hello(version),
+                _ => Response::Error(ErrorCode::InvalidArguments, "HELLO requires a protocol version".to_string()),
+            },
+            _ => return self.format_lists(process_request(request, Some(self.id))),
+        };
+        
+        metrics::record(&command, &response);
+        response
+    }
+    
+    /// Whether the ACL lets this connection run the command. Without an ACL
+    /// everything is allowed.
+    fn permits(&self, command: &str) -> bool {
+        let acl = &configuration::get_config().acl;
+        if acl.is_empty() || PUBLIC_COMMANDS.contains(&command) {
+            return true;
+        }
+        
+        let Some(allowed) = self.token.as_ref().and_then(|token| acl.get(token)) else {
+            return false;
+        };
+        
+        allowed.iter().any(|entry| {
+            entry == "*" || entry.eq_ignore_ascii_case(command) || (entry == "@read" && READ_COMMANDS.contains(&command))
+        })
+    }
+    
+    fn auth(&mut self, token: &str) -> Response {
+        let acl = &configuration::get_config().acl;
+        if !acl.is_empty() && !acl.contains_key(token) {
+            return Response::Error(ErrorCode::Forbidden, "Invalid token".to_string());
+        }
+        
+        self.token = Some(token.to_string());
+        Response::Ok("AUTH OK".to_string())
+    }
+    
+    fn frames_replies(&self) -> bool {
+        self.protocol_version.is_some_and(|version| version >= FRAMED_REPLIES_VERSION)
+    }
+    
+    /// Writes a reply in the form this connection negotiated: framed from
+    /// `FRAMED_REPLIES_VERSION` on, and then gzip compressed above the threshold after COMPRESS ON.
+    fn reply(&self, response: &Response) -> std::io::Result<()> {
+        let mut writer = self.writer.lock().unwrap();
+        
+        if self.frames_replies() {
+            let compress_threshold = self.compress_responses.then(|| configuration::get_config().compression_threshold_bytes);
+            response.write_framed_to(&mut *writer, compress_threshold)
+        } else {
+            response.write_to(&mut *writer)
+        }
+    }
+    
+    /// Renders list responses in the form the negotiated protocol version expects.
+    fn format_lists(&self, response: Response) -> Response {
+        if self.protocol_version.is_some_and(|version| version >= LINE_LISTS_VERSION) {
+            response.into_line_list()
+        } else {
+            response
+        }
+    }
+    
+    fn subscribe(&mut self, container: &str) -> Response {
+        let manager = tree::get_container_manager();
+        
+        if manager.get_template(container).is_none() {
+            return Response::Error(ErrorCode::ContainerNotFound, "Container not found in tree.json".to_string());
+        }
+        
+        let writer = Arc::clone(&self.writer);
+        let events = self.events.get_or_insert_with(|| {
+            let (sender, receiver) = mpsc::channel::<String>();
+            
+            // Ends once the session and every registration have dropped their senders
+            thread::spawn(move || {
+                for message in receiver {
+                    if writer.lock().unwrap().write_all(message.as_bytes()).is_err() {
+                        break;
+                    }
+                }
+            });
+            
+            sender
+        });
+        
+        manager.subscribe(container, self.id, events.clone());
+        
+        if !self.subscriptions.iter().any(|subscribed| subscribed == container) {
+            self.subscriptions.push(container.to_string());
+        }
+        
+        Response::Ok(format!("SUBSCRIBE {}", container))
+    }
+    
+    fn watch(&mut self, container: &str, module: &str) -> Response {
+        if self.queued.is_some() {
+            return Response::Error(ErrorCode::InvalidArguments, "WATCH inside MULTI is not allowed".to_string());
+        }
+        
+        // Taken shared so the version reflects only commands that have completed
+        let _shared = TRANSACTION_LOCK.read().unwrap();
+        let version = tree::get_container_manager().module_version(container, module);
+        self.watched.push((container.to_string(), module.to_string(), version));
+        
+        Response::Ok(format!("WATCH {} {}", container, module))
+    }
+    
+    fn queue(&mut self, command: &str, request: &str) -> Response {
+        if SESSION_COMMANDS.contains(&command) {
+            return Response::Error(ErrorCode::InvalidArguments, format!("{} cannot be used inside MULTI", command));
+        }
+        
+        if !COMMANDS.iter().any(|known| known.name == command) {
+            return Response::Error(ErrorCode::UnknownCommand, format!("Unknown command '{}'", command));
+        }
+        
+        if let Some(queued) = self.queued.as_mut() {
+            queued.push(request.to_string());
+        }
+        
+        Response::Ok("QUEUED".to_string())
+    }
+    
+    /// Runs the queued commands with every other command held off, unless a
+    /// watched module changed since WATCH. Each reply is preceded by its length,
+    /// since replies may span several lines.
+    fn exec(&mut self) -> Response {
+        let Some(queued) = self.queued.take() else {
+            return Response::Error(ErrorCode::InvalidArguments, "EXEC without MULTI".to_string());
+        };
+        let watched = std::mem::take(&mut self.watched);
+        
+        let _exclusive = TRANSACTION_LOCK.write().unwrap();
+        let manager = tree::get_container_manager();
+        
+        if watched.iter().any(|(container, module, version)| manager.module_version(container, module) != *version) {
+            return Response::Error(ErrorCode::TransactionAborted, "Transaction aborted".to_string());
+        }
+        
+        let mut reply = format!("EXEC {}", queued.len());
+        for request in &queued {
+            // Checked again, since RECONFIG may have changed the ACL since the command was queued
+            let command = split_arguments(request).first().map(|command| command.to_uppercase()).unwrap_or_default();
+            let response = if self.permits(&command) {
+                execute_request(request, Some(self.id))
+            } else {
+                Response::Error(ErrorCode::Forbidden, "Forbidden".to_string())
+            };
+            
+            let wire = self.format_lists(response).to_wire();
+            reply.push_str(&format!("\n{}\n{}", wire.len(), wire));
+        }
+        
+        Response::Ok(reply)
+    }
+    
+    fn lock(&self, name: &str, timeout: Duration) -> Response {
+        if tree::get_container_manager().acquire_advisory_lock(name, self.id, timeout) {
+            Response::Ok(format!("LOCK {}", name))
+        } else {
+            Response::Error(ErrorCode::LockTimeout, format!("Timed out waiting for lock '{}'", name))
+        }
+    }
+    
+    /// Settles on the highest version both sides speak. The version cannot change
+    /// once negotiated, since earlier responses were already read under it.
+    fn hello(&mut self, client_version: u32) -> Response {
+        let version = client_version.min(PROTOCOL_VERSION);
+        
+        if let Some(negotiated) = self.protocol_version
+            && negotiated != version
+        {
+            return Response::Error(ErrorCode::InvalidArguments, format!("Protocol version {} already negotiated", negotiated));
+        }
+        
+        if version < MIN_PROTOCOL_VERSION {
+            return Response::Error(
+                ErrorCode::UnsupportedProtocol,
+                format!("Protocol version {} is not supported, minimum is {}", client_version, MIN_PROTOCOL_VERSION),
+            );
+        }
+        
+        self.protocol_version = Some(version);
+        
+        let mut capabilities = CAPABILITIES.to_vec();
+        if !configuration::get_config().acl.is_empty() {
+            capabilities.push(AUTH_CAPABILITY);
+        }
+        Response::Ok(format!("HELLO {} {}", version, capabilities.join(" ")))
+    }
+    
+    fn unsubscribe(&mut self, container: Option<&str>) -> Response {
+        let manager = tree::get_container_manager();
+        
+        let removed: Vec<String> = match container {
+            Some(container) => {
+                let before = self.subscriptions.len();
+                self.subscriptions.retain(|subscribed| subscribed != container);
+                if self.subscriptions.len() == before {
+                    return Response::Error(ErrorCode::NotSubscribed, "Not subscribed".to_string());
+                }
+                vec![container.to_string()]
+            }
+            None => std::mem::take(&mut self.subscriptions),
+        };
+        
+        for container in &removed {
+            manager.unsubscribe(container, self.id);
+        }
+        
+        Response::Ok(format!("UNSUBSCRIBE {}", removed.join(", ")))
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        let manager = tree::get_container_manager();
+        for container in &self.subscriptions {
+            manager.unsubscribe(container, self.id);
+        }
+        manager.release_advisory_locks(self.id);
+    }
+}
+
+/// Keeps a connection counted as active until it is dropped.
+struct ConnectionGuard<'a> {
+    active_connections: &'a AtomicUsize,
+}
+
+impl Drop for ConnectionGuard<'_> {
+    fn drop(&mut self) {
+        self.active_connections.fetch_sub(1, Ordering::SeqCst);
+    }
 }
 
 impl ApiManager {
-    pub fn new() -> Self {
-        let thread_pool_size = num_cpus::get();
+    pub fn new(thread_pool_size: usize) -> Self {
         let thread_pool = ThreadPool::new(thread_pool_size);
         Self {
             thread_pool,
+            active_connections: AtomicUsize::new(0),
+            next_connection_id: AtomicU64::new(1),
         }
     }
 
-    fn handle_connection(mut stream: TcpStream, silent: bool) {
-        let mut buffer = [0; 1024];
+    fn try_acquire_connection(&self, max_connections: usize) -> Option<ConnectionGuard<'_>> {
+        let acquired = self.active_connections.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |active| {
+            if max_connections == 0 || active < max_connections {
+                Some(active + 1)
+            } else {
+                None
+            }
+        });
+        
+        acquired.ok().map(|_| ConnectionGuard {
+            active_connections: &self.active_connections,
+        })
+    }
+
+    fn active_connections(&self) -> usize {
+        self.active_connections.load(Ordering::SeqCst)
+    }
+    
+    fn queue_depth(&self) -> usize {
+        self.thread_pool.queue_depth()
+    }
+
+    /// Serves requests on one client connection until it closes. Any transport
+    /// implementing `Connection` can be served by the same loop, such as one end
+    /// of a Unix socket pair.
+    pub fn handle_connection<S: Connection>(stream: S, silent: bool) {
+        let config = configuration::get_config();
+        
+        if let Err(e) = stream.configure(config)
+            && !silent
+        {
+            eprintln!("Failed to configure socket: {}", e);
+        }
+        
+        let writer: SharedWriter = match stream.try_clone_connection() {
+            Ok(writer) => Arc::new(Mutex::new(writer)),
+            Err(e) => {
+                if !silent {
+                    eprintln!("Failed to set up connection: {}", e);
+                }
+                return;
+            }
+        };
+        
+        let connection_id = get_api_manager().next_connection_id.fetch_add(1, Ordering::SeqCst);
+        let mut session = Session::new(connection_id, writer);
+        
+        // Requests end with the configured delimiter, so payloads of any size arrive whole
+        let delimiter = config.request_delimiter.byte();
+        let mut reader = BufReader::new(stream);
+        let mut buffer = Vec::new();
+        let max_request_bytes = config.max_request_bytes;
+        let mut rate_limiter = (config.rate_limit_per_sec > 0).then(|| RateLimiter::new(config.rate_limit_per_sec));
+        let read_limit = if max_request_bytes == 0 { u64::MAX } else { max_request_bytes as u64 + 1 };
+        
+        let idle_timeout = (config.idle_timeout_secs > 0).then(|| Duration::from_secs(config.idle_timeout_secs));
+        let mut last_activity = Instant::now();
+        
+        if let Err(e) = reader.get_ref().set_read_timeout(idle_timeout)
+            && !silent
+        {
+            eprintln!("Failed to set idle timeout: {}", e);
+        }
         
         loop {
-            match stream.read(&mut buffer) {
-                Ok(0) => break,
-                Ok(n) => {
-                    let request = String::from_utf8_lossy(&buffer[..n]).trim().to_string();
+            // A short read or a transient read error can leave part of a request in the buffer; the next read continues it
+            let remaining = read_limit - buffer.len() as u64;
+            
+            match read_available(&mut (&mut reader).take(remaining), delimiter, &mut buffer) {
+                Ok(0) if buffer.is_empty() => break,
+                Ok(_) if buffer.len() as u64 == read_limit && buffer.last() != Some(&delimiter) => {
+                    let _ = session.reply(&Response::Error(ErrorCode::RequestTooLarge, "Request too large".to_string()));
+                    break;
+                }
+                // A request is complete at its delimiter, or at the end of the stream
+                Ok(read) if read == 0 || buffer.last() == Some(&delimiter) => {
+                    last_activity = Instant::now();
+                    
+                    // Rejected rather than decoded lossily, which would store mangled text
+                    let request = match std::str::from_utf8(&buffer) {
+                        Ok(text) => text.trim_end_matches(char::from(delimiter)).trim_matches(is_argument_separator).to_string(),
+                        Err(_) => {
+                            buffer.clear();
+                            if session.reply(&Response::Error(ErrorCode::InvalidData, "Invalid UTF-8".to_string())).is_err() {
+                                break;
+                            }
+                            continue;
+                        }
+                    };
+                    buffer.clear();
                     
                     if request.is_empty() {
                         continue;
                     }
                     
-                    let response = process_request(&request);
+                    let allowed = rate_limiter.as_mut().is_none_or(|limiter| limiter.try_acquire());
+                    
+                    let json_rpc = session.json_rpc;
+                    let mut execute = |line: &str| {
+                        let response = if allowed {
+                            session.process(line)
+                        } else {
+                            Response::Error(ErrorCode::RateLimited, "Rate limited".to_string())
+                        };
+                        log_command(connection_id, line, &response);
+                        response
+                    };
                     
-                    if let Err(e) = stream.write_all(response.as_bytes()) {
+                    let response = if json_rpc {
+                        match jsonrpc::handle(&request, execute) {
+                            Some(reply) => Response::Ok(reply + "\n"),
+                            None => continue,
+                        }
+                    } else {
+                        execute(&request)
+                    };
+                    
+                    if let Err(e) = session.reply(&response) {
                         if !silent {
-                            eprintln!("Failed to write response: {}", e);
+                            let command = request.split_whitespace().next().unwrap_or_default();
+                            eprintln!("Failed to write {} response to connection {}: {}", command, connection_id, e);
                         }
                         break;
                     }
+                    continue;
                 }
+                // Part of a request or a read timeout; a client trickling bytes is as idle as a silent one
+                Ok(_) => {}
+                Err(e) if is_transient(&e) => {}
                 Err(e) => {
                     if !silent {
-                        eprintln!("Error reading from stream: {}", e);
+                        eprintln!("Error reading from connection {}: {}", connection_id, e);
                     }
                     break;
                 }
             }
+            
+            let Some(idle_timeout) = idle_timeout else {
+                continue;
+            };
+            
+            // Subscribers legitimately sit silent while they wait for events
+            if !session.subscriptions.is_empty() {
+                last_activity = Instant::now();
+            }
+            
+            let idle = last_activity.elapsed();
+            if idle < idle_timeout {
+                let _ = reader.get_ref().set_read_timeout(Some(idle_timeout.saturating_sub(idle).max(Duration::from_millis(1))));
+                continue;
+            }
+            
+            if !silent {
+                println!("Closing connection {} after {}s idle", connection_id, idle.as_secs());
+            }
+            break;
         }
     }
 }
 
+/// Moves what `reader` has available into `buffer`, up to and including the
+/// delimiter, with at most one read. Returns the number of bytes moved.
+fn read_available(reader: &mut impl BufRead, delimiter: u8, buffer: &mut Vec<u8>) -> std::io::Result<usize> {
+    let available = reader.fill_buf()?;
+    let length = available.iter().position(|&byte| byte == delimiter).map_or(available.len(), |position| position + 1);
+    buffer.extend_from_slice(&available[..length]);
+    reader.consume(length);
+    Ok(length)
+}
+
+/// Read errors that leave the connection usable, such as a read timeout.
+fn is_transient(error: &std::io::Error) -> bool {
+    matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted)
+}
+
+/// Write half of a connection, shared between request replies and change notifications.
+type SharedWriter = Arc<Mutex<dyn Write + Send>>;
+
+/// A client stream the request loop can serve.
+pub trait Connection: Read + Write + Send + Sized + 'static {
+    /// Opens a second handle to the same stream for writing.
+    fn try_clone_connection(&self) -> std::io::Result<Self>;
+    
+    /// Applies transport specific options from the configuration.
+    fn configure(&self, _config: &Config) -> std::io::Result<()> {
+        Ok(())
+    }
+    
+    /// Bounds how long a single read may block, `None` to block indefinitely.
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()>;
+}
+
+impl Connection for TcpStream {
+    fn try_clone_connection(&self) -> std::io::Result<Self> {
+        self.try_clone()
+    }
+    
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+    
+    fn configure(&self, config: &Config) -> std::io::Result<()> {
+        self.set_nodelay(config.tcp_nodelay)?;
+        SockRef::from(self).set_keepalive(config.tcp_keepalive)
+    }
+}
+
+#[cfg(unix)]
+impl Connection for std::os::unix::net::UnixStream {
+    fn try_clone_connection(&self) -> std::io::Result<Self> {
+        self.try_clone()
+    }
+    
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        std::os::unix::net::UnixStream::set_read_timeout(self, timeout)
+    }
+}
+
 pub fn get_api_manager() -> &'static ApiManager {
-    API_MANAGER.get_or_init(|| ApiManager::new())
+    API_MANAGER.get_or_init(|| ApiManager::new(configuration::get_config().worker_threads()))
 }
 
 pub fn start_server(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
-    let manager = get_api_manager();
-    let listener = TcpListener::bind(config.address())?;
+    let (listener, _) = bind_server(config)?;
+    spawn_shutdown_handler(config.silent)?;
+    serve(listener)
+}
+
+/// Exits cleanly on SIGINT or SIGTERM, or Ctrl-C where there are no signals. Running
+/// commands finish first, new ones are held back and staged writes reach the disk.
+fn spawn_shutdown_handler(silent: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    let signals = {
+        let _context = runtime.enter();
+        ShutdownSignals::register()?
+    };
+    
+    thread::spawn(move || {
+        runtime.block_on(signals.received());
+        
+        if !silent {
+            println!("Shutting down, finishing pending writes");
+        }
+        
+        // Kept until exit, so no command starts after the flush
+        let _exclusive = drain_for_shutdown();
+        std::process::exit(0);
+    });
+    
+    Ok(())
+}
+
+/// Lets running commands finish, holds back new ones and waits until staged
+/// writes are on disk. Commands stay held back while the returned guard lives.
+pub fn drain_for_shutdown() -> RwLockWriteGuard<'static, ()> {
+    let exclusive = TRANSACTION_LOCK.write().unwrap();
+    tree::flush_staged_writes();
+    exclusive
+}
+
+#[cfg(unix)]
+struct ShutdownSignals {
+    interrupt: tokio::signal::unix::Signal,
+    terminate: tokio::signal::unix::Signal,
+}
+
+#[cfg(unix)]
+impl ShutdownSignals {
+    /// Replaces the default handlers right away, so a signal arriving before the
+    /// handler thread runs is not lost.
+    fn register() -> std::io::Result<Self> {
+        use tokio::signal::unix::{SignalKind, signal};
+        
+        Ok(Self {
+            interrupt: signal(SignalKind::interrupt())?,
+            terminate: signal(SignalKind::terminate())?,
+        })
+    }
+    
+    async fn received(mut self) {
+        tokio::select! {
+            _ = self.interrupt.recv() => {}
+            _ = self.terminate.recv() => {}
+        }
+    }
+}
+
+#[cfg(not(unix))]
+struct ShutdownSignals;
+
+#[cfg(not(unix))]
+impl ShutdownSignals {
+    fn register() -> std::io::Result<Self> {
+        Ok(Self)
+    }
+    
+    async fn received(self) {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+/// Binds the listener and announces it, returning the address actually bound,
+/// which differs from the configured one when `port` is 0.
+pub fn bind_server(config: &Config) -> Result<(TcpListener, SocketAddr), Box<dyn std::error::Error>> {
+    let listener = bind_listener(config)?;
+    let address = listener.local_addr()?;
     
     if !config.silent {
-        println!("Triangular Database listening on {}", config.address());
+        println!("Triangular Database listening on {}", address);
     }
     
-    let silent = config.silent;
+    signal_ready(config, address)?;
+    
+    Ok((listener, address))
+}
+
+/// Builds the listening socket with the configured `backlog` and `reuse_address`.
+fn bind_listener(config: &Config) -> Result<TcpListener, Box<dyn std::error::Error>> {
+    let address = config.address().to_socket_addrs()?.next().ok_or_else(|| format!("Cannot resolve {}", config.address()))?;
+    
+    let socket = Socket::new(Domain::for_address(address), Type::STREAM, Some(Protocol::TCP))?;
+    socket.set_reuse_address(config.reuse_address)?;
+    socket.bind(&address.into())?;
+    socket.listen(i32::try_from(config.backlog).unwrap_or(i32::MAX))?;
+    
+    Ok(socket.into())
+}
+
+/// Accepts connections until the listener fails for good. Failed accepts, such as
+/// running out of file descriptors, are logged and retried after a growing pause.
+pub fn serve(listener: TcpListener) -> Result<(), Box<dyn std::error::Error>> {
+    let manager = get_api_manager();
+    let mut backoff = ACCEPT_BACKOFF_START;
     
     for stream in listener.incoming() {
-        let stream = stream?;
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(e) if is_fatal_accept_error(&e) => return Err(e.into()),
+            Err(e) => {
+                if !configuration::get_config().silent {
+                    eprintln!("Failed to accept connection, retrying in {} ms: {}", backoff.as_millis(), e);
+                }
+                thread::sleep(backoff);
+                backoff = (backoff * 2).min(ACCEPT_BACKOFF_MAX);
+                continue;
+            }
+        };
+        backoff = ACCEPT_BACKOFF_START;
+        
+        // Re-read for every connection so RECONFIG applies to new clients
+        let config = configuration::get_config();
+        let silent = config.silent;
+        
+        let guard = match manager.try_acquire_connection(config.max_connections) {
+            Some(guard) => guard,
+            None => {
+                let _ = stream.write_all(Response::Error(ErrorCode::TooManyConnections, "Too many connections".to_string()).to_wire().as_bytes());
+                continue;
+            }
+        };
         
         manager.thread_pool.execute(move || {
+            let _guard = guard;
             ApiManager::handle_connection(stream, silent);
         });
     }
@@ -114,41 +1149,252 @@ pub fn start_server(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-pub fn process_request(request: &str) -> String {
-    let parts: Vec<&str> = request.split_whitespace().collect();
+/// Accept errors that mean the listener itself is unusable, as opposed to a
+/// single connection failing or a temporary lack of resources.
+fn is_fatal_accept_error(error: &std::io::Error) -> bool {
+    matches!(
+        error.kind(),
+        ErrorKind::InvalidInput | ErrorKind::NotConnected | ErrorKind::Unsupported | ErrorKind::PermissionDenied
+    )
+}
+
+/// Tells a supervisor that the listener is bound: a `READY <address>` line on
+/// stdout unless silent, and the address written to `ready_file` if configured.
+fn signal_ready(config: &Config, address: SocketAddr) -> Result<(), Box<dyn std::error::Error>> {
+    if !config.silent {
+        println!("READY {}", address);
+        std::io::stdout().flush()?;
+    }
+    
+    if !config.ready_file.is_empty() {
+        std::fs::write(&config.ready_file, address.to_string())?;
+    }
+    
+    Ok(())
+}
+
+/// Prints the command and its result status when `log_commands` is on.
+fn log_command(connection_id: u64, request: &str, response: &Response) {
+    if !configuration::get_config().log_commands {
+        return;
+    }
+    
+    let command = request.split_whitespace().next().unwrap_or_default().to_uppercase();
+    let status = match response {
+        Response::Error(code, _) => code.as_str(),
+        _ => "OK",
+    };
+    println!("[connection {}] {} {}", connection_id, command, status);
+}
+
+/// Runs a single command. `connection_id` identifies the client that sent it, so
+/// change notifications are not echoed back to the connection that caused them.
+pub fn process_request(request: &str, connection_id: Option<u64>) -> Response {
+    let _shared = TRANSACTION_LOCK.read().unwrap();
+    execute_request(request, connection_id)
+}
+
+/// Runs one request; callers hold `TRANSACTION_LOCK`.
+fn execute_request(request: &str, connection_id: Option<u64>) -> Response {
+    let parts = split_arguments(request);
     
     if parts.is_empty() {
-        return "ERROR: Empty request".to_string();
+        return Response::Error(ErrorCode::InvalidArguments, "Empty request".to_string());
     }
     
     let command = parts[0].to_uppercase();
     
-    match command.as_str() {
+    // A retried INIT gets the reply of the first attempt instead of running again
+    let idempotency = match (command.as_str(), parts.get(1), parts.get(2)) {
+        ("INIT", Some(&container), Some(&module)) => InitOptions::parse(&parts)
+            .ok()
+            .and_then(|options| options.idempotency_token)
+            .map(|token| (container, module, token)),
+        _ => None,
+    };
+    if let Some((container, module, token)) = idempotency
+        && let Some(response) = tree::get_container_manager().begin_idempotent(container, module, token)
+    {
+        metrics::record(&command, &response);
+        return response;
+    }
+    
+    let mut response = dispatch(&command, &parts, request);
+    
+    if let Err(e) = tree::await_staged_writes() {
+        response = e;
+    }
+    if let Some((container, module, token)) = idempotency {
+        tree::get_container_manager().finish_idempotent(container, module, token, &response);
+    }
+    metrics::record(&command, &response);
+    
+    // SETNX answers EXISTS without changing anything
+    let changed = match &response {
+        Response::Ok(message) => command != "SETNX" || message != "EXISTS",
+        Response::Error(..) | Response::Json(_) | Response::JsonStream(_) | Response::List(_) | Response::Lines(_) => false,
+    };
+    
+    if changed && is_mutating(&command, &parts) {
+        let entry = audit_entry(&command, &parts, request);
+        audit::record(&entry);
+        bump_versions(&entry);
+        publish_change(&entry, connection_id);
+    }
+    
+    response
+}
+
+/// Whether a request changes stored data. CMETA only does with SET.
+fn is_mutating(command: &str, parts: &[&str]) -> bool {
+    match command {
+        "CMETA" => parts.get(1).is_some_and(|action| action.eq_ignore_ascii_case("SET")),
+        _ => MUTATING_COMMANDS.contains(&command),
+    }
+}
+
+/// Marks the modules a change touched as modified for WATCH. Commands that do not
+/// name a single module count as a change to the whole container.
+fn bump_versions(entry: &AuditEntry) {
+    let manager = tree::get_container_manager();
+    let Some(container) = entry.container else {
+        return;
+    };
+    
+    match (entry.command, entry.module) {
+        ("MOVE", Some(module)) => {
+            manager.bump_module_version(container, module);
+            if let Some(target) = entry.value {
+                manager.bump_module_version(target, module);
+            }
+        }
+        ("IMPORT" | "INITJSON" | "REPAIR", _) | (_, None) => manager.bump_container_version(container),
+        (_, Some(module)) => manager.bump_module_version(container, module),
+    }
+}
+
+fn dispatch(command: &str, parts: &[&str], request: &str) -> Response {
+    match command {
+        "PING" => {
+            if parts.len() > 1 {
+                Response::Ok(trailing_argument(request, 1).to_string())
+            } else {
+                Response::Ok("PONG".to_string())
+            }
+        }
+        "VERSION" => Response::Ok(format!("triangular-database {} protocol {}", env!("CARGO_PKG_VERSION"), PROTOCOL_VERSION)),
         "INIT" => {
             if parts.len() < 3 {
-                return "ERROR: INIT requires container and value".to_string();
+                return Response::Error(ErrorCode::InvalidArguments, "INIT requires container and value".to_string());
             }
             
-            let container = parts[1];
-            let value = parts[2];
+            let options = match InitOptions::parse(parts) {
+                Ok(options) => options,
+                Err(e) => return e,
+            };
             
-            tree::handle_init(container, value)
+            tree::handle_init(parts[1], parts[2], &options.fields, options.ttl_seconds)
         }
         "SET" => {
             if parts.len() < 5 {
-                return "ERROR: SET requires container, module, key, and value".to_string();
+                return Response::Error(ErrorCode::InvalidArguments, "SET requires container, module, key, and value".to_string());
             }
             
             let container = parts[1];
             let module = parts[2];
             let key = parts[3];
-            let value = parts[4];
+            let value = value_argument(parts, request, 4);
             
             tree::handle_set(container, module, key, value)
         }
+        "SETM" => {
+            if parts.len() < 5 {
+                return Response::Error(ErrorCode::InvalidArguments, "SETM requires key/value pairs".to_string());
+            }
+            
+            let container = parts[1];
+            let module = parts[2];
+            // An odd number of arguments after the module means the first one is the flag
+            let partial = parts.len().is_multiple_of(2);
+            if partial && !parts[3].eq_ignore_ascii_case("PARTIAL") {
+                return Response::Error(ErrorCode::InvalidArguments, "SETM requires key/value pairs".to_string());
+            }
+            
+            let first_pair = if partial { 4 } else { 3 };
+            let pairs: Vec<(&str, &str)> = parts[first_pair..].chunks(2).map(|pair| (pair[0], pair[1])).collect();
+            
+            tree::handle_set_many(container, module, &pairs, partial)
+        }
+        "SETNX" => {
+            if parts.len() < 5 {
+                return Response::Error(ErrorCode::InvalidArguments, "SETNX requires container, module, key, and value".to_string());
+            }
+            
+            tree::handle_setnx(parts[1], parts[2], parts[3], value_argument(parts, request, 4))
+        }
+        "CAS" => {
+            if parts.len() < 6 {
+                return Response::Error(ErrorCode::InvalidArguments, "CAS requires container, module, key, expected and new value".to_string());
+            }
+            
+            tree::handle_cas(parts[1], parts[2], parts[3], parts[4], value_argument(parts, request, 5))
+        }
+        "SETJSON" => {
+            if parts.len() < 5 {
+                return Response::Error(ErrorCode::InvalidArguments, "SETJSON requires container, module, key, and JSON value".to_string());
+            }
+            
+            tree::handle_set_json(parts[1], parts[2], parts[3], trailing_argument(request, 4))
+        }
+        "SETBIN" => {
+            if parts.len() < 5 {
+                return Response::Error(ErrorCode::InvalidArguments, "SETBIN requires container, module, key, and base64 value".to_string());
+            }
+            
+            let container = parts[1];
+            let module = parts[2];
+            let key = parts[3];
+            let value = parts[4];
+            
+            tree::handle_set_binary(container, module, key, value)
+        }
+        "DECR" => {
+            if parts.len() < 4 {
+                return Response::Error(ErrorCode::InvalidArguments, "DECR requires container, module, and key".to_string());
+            }
+            
+            let container = parts[1];
+            let module = parts[2];
+            let key = parts[3];
+            
+            let amount = match parts.get(4).map(|amount| amount.parse::<i64>()) {
+                None => 1,
+                Some(Ok(amount)) => amount,
+                Some(Err(_)) => return Response::Error(ErrorCode::InvalidArguments, "DECR amount must be an integer".to_string()),
+            };
+            
+            tree::handle_decr(container, module, key, amount)
+        }
+        "EXPIRE" => {
+            if parts.len() != 4 {
+                return Response::Error(ErrorCode::InvalidArguments, "EXPIRE requires container, module, and seconds".to_string());
+            }
+            
+            match parts[3].parse::<u64>() {
+                Ok(seconds) if seconds > 0 => tree::handle_expire(parts[1], parts[2], Some(seconds)),
+                _ => Response::Error(ErrorCode::InvalidArguments, "EXPIRE seconds must be a positive number".to_string()),
+            }
+        }
+        "PERSIST" => {
+            if parts.len() != 3 {
+                return Response::Error(ErrorCode::InvalidArguments, "PERSIST requires container and module".to_string());
+            }
+            
+            tree::handle_expire(parts[1], parts[2], None)
+        }
         "GET" => {
             if parts.len() < 4 {
-                return "ERROR: GET requires container, module, and key".to_string();
+                return Response::Error(ErrorCode::InvalidArguments, "GET requires container, module, and key".to_string());
             }
             
             let container = parts[1];
@@ -157,22 +1403,413 @@ pub fn process_request(request: &str) -> String {
             
             tree::handle_get(container, module, key)
         }
+        "MGET" => {
+            if parts.len() < 4 {
+                return Response::Error(ErrorCode::InvalidArguments, "MGET requires container, module, and at least one key".to_string());
+            }
+            
+            let container = parts[1];
+            let module = parts[2];
+            
+            tree::handle_mget(container, module, &parts[3..])
+        }
+        "GETALL" => {
+            if parts.len() < 3 {
+                return Response::Error(ErrorCode::InvalidArguments, "GETALL requires container and module".to_string());
+            }
+            
+            let container = parts[1];
+            let module = parts[2];
+            
+            tree::handle_get_all(container, module)
+        }
+        "MGETALL" => {
+            if parts.len() < 3 {
+                return Response::Error(ErrorCode::InvalidArguments, "MGETALL requires container and at least one module".to_string());
+            }
+            
+            tree::handle_mget_all(parts[1], &parts[2..])
+        }
+        "VER" => {
+            if parts.len() != 3 {
+                return Response::Error(ErrorCode::InvalidArguments, "VER requires container and module".to_string());
+            }
+            
+            tree::handle_module_version(parts[1], parts[2])
+        }
+        "CONTAINERS" => tree::handle_list_containers(),
+        "SIZE" if parts.len() > 2 => Response::Error(ErrorCode::InvalidArguments, "SIZE takes 0 or 1 arguments".to_string()),
+        "SIZE" => tree::handle_size(parts.get(1).copied()),
         "LIST" => {
             if parts.len() < 2 {
-                return "ERROR: LIST requires container".to_string();
+                return Response::Error(ErrorCode::InvalidArguments, "LIST requires container".to_string());
             }
             
             let container = parts[1];
             
             if parts.len() == 2 {
-                tree::handle_list_modules(container)
+                tree::handle_list_modules(container, &tree::ListOptions::default())
             } else if parts.len() == 3 {
                 let module = parts[2];
-                tree::handle_list_keys(container, module)
+                tree::handle_list_keys(container, module, false)
+            } else if parts.len() == 4 && parts[3].eq_ignore_ascii_case("WITHVERSION") {
+                tree::handle_list_keys(container, parts[2], true)
+            } else {
+                let mut options = tree::ListOptions::default();
+                let mut arguments = parts[2..].iter().peekable();
+                
+                while let Some(name) = arguments.next() {
+                    let Some(value) = arguments.next() else {
+                        return Response::Error(ErrorCode::InvalidArguments, "LIST options require a value".to_string());
+                    };
+                    
+                    match name.to_uppercase().as_str() {
+                        "SORT" => {
+                            options.sort_key = Some(value);
+                            
+                            if let Some(direction) = arguments.next_if(|direction| matches!(direction.to_uppercase().as_str(), "ASC" | "DESC")) {
+                                options.descending = direction.eq_ignore_ascii_case("DESC");
+                            }
+                        }
+                        "PREFIX" => options.prefix = Some(value),
+                        "LIMIT" => match value.parse() {
+                            Ok(limit) => options.limit = Some(limit),
+                            Err(_) => return Response::Error(ErrorCode::InvalidArguments, "LIMIT must be a non-negative integer".to_string()),
+                        },
+                        "OFFSET" => match value.parse() {
+                            Ok(offset) => options.offset = offset,
+                            Err(_) => return Response::Error(ErrorCode::InvalidArguments, "OFFSET must be a non-negative integer".to_string()),
+                        },
+                        _ => return Response::Error(ErrorCode::InvalidArguments, format!("Unknown LIST option '{}'", name)),
+                    }
+                }
+                
+                tree::handle_list_modules(container, &options)
+            }
+        }
+        "FIND" => {
+            if parts.len() < 5 {
+                return Response::Error(ErrorCode::InvalidArguments, "FIND requires container, key, operator and value".to_string());
+            }
+            
+            match tree::Comparison::parse(parts[3]) {
+                Some(comparison) => tree::handle_find(parts[1], parts[2], comparison, parts[4]),
+                None => Response::Error(ErrorCode::InvalidArguments, "Unknown operator, expected eq, ne, gt, lt, gte or lte".to_string()),
+            }
+        }
+        "PLUCK" => {
+            if parts.len() != 3 {
+                return Response::Error(ErrorCode::InvalidArguments, "PLUCK requires container and key".to_string());
+            }
+            
+            tree::handle_pluck(parts[1], parts[2])
+        }
+        "DUMP" => {
+            if parts.len() == 1 {
+                tree::handle_dump_all()
+            } else if parts.len() == 2 {
+                tree::handle_dump(parts[1])
             } else {
-                "ERROR: LIST takes 1 or 2 arguments".to_string()
+                Response::Error(ErrorCode::InvalidArguments, "DUMP takes 0 or 1 arguments".to_string())
+            }
+        }
+        "INITJSON" => {
+            if parts.len() < 3 {
+                return Response::Error(ErrorCode::InvalidArguments, "INITJSON requires container and JSON object".to_string());
+            }
+            
+            tree::handle_init_json(parts[1], trailing_argument(request, 2))
+        }
+        "BULKINIT" => {
+            if parts.len() < 3 {
+                return Response::Error(ErrorCode::InvalidArguments, "BULKINIT requires container and JSON array".to_string());
+            }
+            
+            tree::handle_bulk_init(parts[1], trailing_argument(request, 2))
+        }
+        "IMPORT" => {
+            if parts.len() < 3 {
+                return Response::Error(ErrorCode::InvalidArguments, "IMPORT requires container and JSON array".to_string());
+            }
+            
+            let container = parts[1];
+            let payload = trailing_argument(request, 2);
+            
+            tree::handle_import(container, payload)
+        }
+        "REPAIR" => {
+            if parts.len() < 2 {
+                return Response::Error(ErrorCode::InvalidArguments, "REPAIR requires container".to_string());
+            }
+            
+            tree::handle_repair(parts[1])
+        }
+        "VERIFY" => tree::handle_verify(),
+        "COMPACT" => {
+            if parts.len() != 2 {
+                return Response::Error(ErrorCode::InvalidArguments, "COMPACT requires container".to_string());
+            }
+            
+            tree::handle_compact(parts[1])
+        }
+        "MOVE" => {
+            if parts.len() != 4 {
+                return Response::Error(ErrorCode::InvalidArguments, "MOVE requires source container, module and target container".to_string());
+            }
+            
+            tree::handle_move(parts[1], parts[2], parts[3])
+        }
+        "RELOAD" => tree::handle_reload(),
+        "CMETA" => match parts.get(1).map(|action| action.to_uppercase()).as_deref() {
+            Some("GET") if parts.len() == 4 => tree::handle_meta_get(parts[2], parts[3]),
+            Some("SET") if parts.len() >= 5 => tree::handle_meta_set(parts[2], parts[3], trailing_argument(request, 4)),
+            _ => Response::Error(ErrorCode::InvalidArguments, "CMETA requires GET <container> <key> or SET <container> <key> <value>".to_string()),
+        },
+        "HELP" => match parts.get(1) {
+            None => Response::Ok(COMMANDS.iter().map(|spec| spec.syntax).collect::<Vec<_>>().join("\n")),
+            Some(name) => match COMMANDS.iter().find(|spec| spec.name.eq_ignore_ascii_case(name)) {
+                Some(spec) => Response::Ok(format!("{}\n{}", spec.syntax, spec.summary)),
+                None => Response::Error(ErrorCode::UnknownCommand, format!("Unknown command '{}'", name)),
+            },
+        },
+        "DRYRUN" => {
+            let inner = trailing_argument(request, 1);
+            let inner_parts = split_arguments(inner);
+            
+            let Some(inner_command) = inner_parts.first().map(|command| command.to_uppercase()) else {
+                return Response::Error(ErrorCode::InvalidArguments, "DRYRUN requires a command".to_string());
+            };
+            
+            if !is_mutating(&inner_command, &inner_parts) {
+                return Response::Error(ErrorCode::InvalidArguments, "DRYRUN only applies to mutating commands".to_string());
+            }
+            
+            match tree::dry_run(|| dispatch(&inner_command, &inner_parts, inner)) {
+                Response::Ok(message) => Response::Ok(format!("DRYRUN {}", message)),
+                error => error,
+            }
+        }
+        "STATS" => {
+            let manager = get_api_manager();
+            Response::Ok(metrics::render_stats(manager.active_connections(), manager.queue_depth()))
+        }
+        "METRICS" => {
+            let manager = get_api_manager();
+            Response::Ok(metrics::render_prometheus(manager.active_connections(), manager.queue_depth()))
+        }
+        "RECONFIG" => reconfigure(),
+        _ => match suggest_command(command) {
+            Some(suggestion) => Response::Error(ErrorCode::UnknownCommand, format!("Unknown command '{}', did you mean '{}'?", parts[0], suggestion)),
+            None => Response::Error(ErrorCode::UnknownCommand, format!("Unknown command '{}'", parts[0])),
+        },
+    }
+}
+
+fn reconfigure() -> Response {
+    let (config, pending) = match configuration::reload_config() {
+        Ok(result) => result,
+        Err(e) => return Response::Error(ErrorCode::InvalidData, format!("Failed to reload configuration: {}", e)),
+    };
+    
+    if let Err(e) = tree::get_container_manager().load_schemas(&config.schemas) {
+        return Response::Error(ErrorCode::InvalidData, format!("Configuration reloaded but schemas were not: {}", e));
+    }
+    
+    if pending.is_empty() {
+        Response::Ok("Configuration reloaded".to_string())
+    } else {
+        Response::Ok(format!("Configuration reloaded; restart required for: {}", pending.join(", ")))
+    }
+}
+
+/// Finds the known command closest to a mistyped one, if any is within two edits.
+fn suggest_command(command: &str) -> Option<&'static str> {
+    COMMANDS.iter()
+        .map(|known| (known.name, edit_distance(command, known.name)))
+        .filter(|(_, distance)| *distance <= 2)
+        .min_by_key(|(_, distance)| *distance)
+        .map(|(known, _)| known)
+}
+
+/// Edit distance between two strings, counting a swap of adjacent characters
+/// as a single edit since that is the most common typo.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+    
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + cost);
+            
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            
+            distances[i][j] = distance;
+        }
+    }
+    
+    distances[a.len()][b.len()]
+}
+
+/// Notifies subscribers of the affected container with an `EVENT <container> <command> [<module>]` line.
+fn publish_change(entry: &AuditEntry, connection_id: Option<u64>) {
+    let Some(container) = entry.container else {
+        return;
+    };
+    
+    let message = match entry.module {
+        Some(module) => format!("EVENT {} {} {}\n", container, entry.command, module),
+        None => format!("EVENT {} {}\n", container, entry.command),
+    };
+    
+    let manager = tree::get_container_manager();
+    manager.publish(container, connection_id, &message);
+    
+    // MOVE also changes its target container
+    if entry.command == "MOVE"
+        && let Some(target) = entry.value
+        && target != container
+    {
+        manager.publish(target, connection_id, &message);
+    }
+}
+
+/// Options and fields following `INIT <container> <id>`.
+struct InitOptions<'a> {
+    ttl_seconds: Option<u64>,
+    idempotency_token: Option<&'a str>,
+    fields: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> InitOptions<'a> {
+    fn parse(parts: &[&'a str]) -> Result<Self, Response> {
+        let mut options = InitOptions { ttl_seconds: None, idempotency_token: None, fields: Vec::new() };
+        let mut rest = parts.get(3..).unwrap_or_default();
+        
+        loop {
+            match rest.first().map(|option| option.to_uppercase()).as_deref() {
+                Some("TTL") if options.ttl_seconds.is_none() => match rest.get(1).map(|seconds| seconds.parse::<u64>()) {
+                    Some(Ok(seconds)) if seconds > 0 => options.ttl_seconds = Some(seconds),
+                    _ => return Err(Response::Error(ErrorCode::InvalidArguments, "TTL must be a positive number of seconds".to_string())),
+                },
+                Some("IDEM") if options.idempotency_token.is_none() => match rest.get(1) {
+                    Some(token) => options.idempotency_token = Some(token),
+                    None => return Err(Response::Error(ErrorCode::InvalidArguments, "IDEM requires a token".to_string())),
+                },
+                _ => break,
             }
+            rest = &rest[2..];
         }
-        _ => "ERROR: Unknown command".to_string(),
+        
+        for pair in rest {
+            match pair.split_once('=') {
+                Some((key, field_value)) if !key.is_empty() => options.fields.push((key, field_value)),
+                _ => return Err(Response::Error(ErrorCode::InvalidArguments, "INIT fields must be key=value pairs".to_string())),
+            }
+        }
+        
+        Ok(options)
+    }
+}
+
+/// Maps a command's arguments onto audit fields. Most commands take
+/// `<container> <module> <key> <value>`; the rest are listed explicitly.
+fn audit_entry<'a>(command: &'a str, parts: &[&'a str], request: &'a str) -> AuditEntry<'a> {
+    match command {
+        "INIT" => AuditEntry {
+            command,
+            container: parts.get(1).copied(),
+            module: parts.get(2).copied(),
+            key: None,
+            value: Some(trailing_argument(request, 3)).filter(|fields| !fields.is_empty()),
+        },
+        "CAS" => AuditEntry {
+            command,
+            container: parts.get(1).copied(),
+            module: parts.get(2).copied(),
+            key: parts.get(3).copied(),
+            value: parts.get(5).map(|_| value_argument(parts, request, 5)),
+        },
+        "SETM" => AuditEntry {
+            command,
+            container: parts.get(1).copied(),
+            module: parts.get(2).copied(),
+            key: None,
+            value: Some(trailing_argument(request, 3)),
+        },
+        "MOVE" => AuditEntry {
+            command,
+            container: parts.get(1).copied(),
+            module: parts.get(2).copied(),
+            key: None,
+            value: parts.get(3).copied(),
+        },
+        "CMETA" => AuditEntry {
+            command,
+            container: parts.get(2).copied(),
+            module: None,
+            key: parts.get(3).copied(),
+            value: Some(trailing_argument(request, 4)),
+        },
+        "IMPORT" | "INITJSON" | "BULKINIT" => AuditEntry {
+            command,
+            container: parts.get(1).copied(),
+            module: None,
+            key: None,
+            value: Some(trailing_argument(request, 2)),
+        },
+        _ => AuditEntry {
+            command,
+            container: parts.get(1).copied(),
+            module: parts.get(2).copied(),
+            key: parts.get(3).copied(),
+            value: Some(trailing_argument(request, 4)).filter(|value| !value.is_empty()),
+        },
     }
-} 
\ No newline at end of file
+}
+
+/// Whether `c` separates the arguments of a request. NUL delimited requests are
+/// only split on spaces, so values may contain newlines and tabs.
+fn is_argument_separator(c: char) -> bool {
+    match configuration::get_config().request_delimiter {
+        RequestDelimiter::Newline => c.is_whitespace(),
+        RequestDelimiter::Null => c == ' ',
+    }
+}
+
+fn split_arguments(request: &str) -> Vec<&str> {
+    request.split(is_argument_separator).filter(|argument| !argument.is_empty()).collect()
+}
+
+/// The value a command takes as its last argument. With NUL delimited requests it
+/// is the rest of the request, so it may contain spaces and newlines.
+fn value_argument<'a>(parts: &[&'a str], request: &'a str, index: usize) -> &'a str {
+    match configuration::get_config().request_delimiter {
+        RequestDelimiter::Newline => parts[index],
+        RequestDelimiter::Null => trailing_argument(request, index),
+    }
+}
+
+/// Returns the raw text after the first `count` arguments, for payloads that may contain spaces.
+fn trailing_argument(request: &str, count: usize) -> &str {
+    let mut rest = request.trim_start_matches(is_argument_separator);
+    
+    for _ in 0..count {
+        let end = rest.find(is_argument_separator).unwrap_or(rest.len());
+        rest = rest[end..].trim_start_matches(is_argument_separator);
+    }
+    
+    rest
+}
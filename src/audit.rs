@@ -0,0 +1,57 @@
+// Copyright (c) 2025, TheByteSlayer, Triangular
+// Stores structured Data in JSON Files and makes it accessible over TCP, written in Rust.
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::configuration;
+
+static AUDIT_LOCK: Mutex<()> = Mutex::new(());
+
+/// A single mutating command as it is written to the audit log.
+pub struct AuditEntry<'a> {
+    pub command: &'a str,
+    pub container: Option<&'a str>,
+    pub module: Option<&'a str>,
+    pub key: Option<&'a str>,
+    pub value: Option<&'a str>,
+}
+
+/// Appends an entry to the configured audit log as one JSON line. Does nothing
+/// when `audit_log` is empty.
+pub fn record(entry: &AuditEntry) {
+    let config = configuration::get_config();
+    
+    if config.audit_log.is_empty() {
+        return;
+    }
+    
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or(0);
+    
+    let line = serde_json::json!({
+        "timestamp": timestamp,
+        "command": entry.command,
+        "container": entry.container,
+        "module": entry.module,
+        "key": entry.key,
+        "value": entry.value,
+    });
+    
+    let _guard = AUDIT_LOCK.lock().unwrap();
+    
+    let result = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&config.audit_log)
+        .and_then(|mut file| writeln!(file, "{}", line));
+    
+    if let Err(e) = result
+        && !config.silent
+    {
+        eprintln!("Failed to write audit log: {}", e);
+    }
+}
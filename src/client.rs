@@ -0,0 +1,191 @@
+// Copyright (c) 2025, TheByteSlayer, Triangular
+// Stores structured Data in JSON Files and makes it accessible over TCP, written in Rust.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use flate2::read::GzDecoder;
+use crate::api::FRAMED_REPLIES_VERSION;
+use crate::configuration::RequestDelimiter;
+
+#[derive(Debug)]
+pub enum ClientError {
+    Io(io::Error),
+    /// The server answered with an `ERROR[<code>]: <message>` response.
+    Server { code: String, message: String },
+    /// An argument contains whitespace, which the line protocol cannot carry.
+    InvalidArgument(String),
+    /// The server sent something other than a framed reply.
+    Protocol(String),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Io(e) => write!(f, "I/O error: {}", e),
+            ClientError::Server { code, message } => write!(f, "Server error {}: {}", code, message),
+            ClientError::InvalidArgument(argument) => write!(f, "Invalid argument: '{}'", argument),
+            ClientError::Protocol(message) => write!(f, "Protocol error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<io::Error> for ClientError {
+    fn from(e: io::Error) -> Self {
+        ClientError::Io(e)
+    }
+}
+
+/// Blocking client for the Triangular Database TCP protocol.
+pub struct Client {
+    stream: TcpStream,
+    reader: BufReader<TcpStream>,
+    delimiter: RequestDelimiter,
+    /// EVENT lines that arrived ahead of a reply, oldest first.
+    events: VecDeque<String>,
+}
+
+impl Client {
+    pub fn connect<A: ToSocketAddrs>(address: A) -> Result<Self, ClientError> {
+        Self::connect_with_delimiter(address, RequestDelimiter::Newline)
+    }
+
+    /// Connects to a server whose `request_delimiter` is `delimiter`.
+    pub fn connect_with_delimiter<A: ToSocketAddrs>(address: A, delimiter: RequestDelimiter) -> Result<Self, ClientError> {
+        let stream = TcpStream::connect(address)?;
+        let reader = BufReader::new(stream.try_clone()?);
+        let mut client = Self { stream, reader, delimiter, events: VecDeque::new() };
+        
+        // Framed replies are how the client finds where each reply ends
+        client.command(&["HELLO", &FRAMED_REPLIES_VERSION.to_string()])?;
+        Ok(client)
+    }
+
+    pub fn ping(&mut self) -> Result<String, ClientError> {
+        self.command(&["PING"])
+    }
+
+    pub fn init(&mut self, container: &str, value: &str) -> Result<String, ClientError> {
+        self.command(&["INIT", container, value])
+    }
+
+    pub fn set(&mut self, container: &str, module: &str, key: &str, value: &str) -> Result<String, ClientError> {
+        self.command(&["SET", container, module, key, value])
+    }
+
+    pub fn get(&mut self, container: &str, module: &str, key: &str) -> Result<String, ClientError> {
+        self.command(&["GET", container, module, key])
+    }
+
+    pub fn get_all(&mut self, container: &str, module: &str) -> Result<String, ClientError> {
+        self.command(&["GETALL", container, module])
+    }
+
+    pub fn list_modules(&mut self, container: &str) -> Result<Vec<String>, ClientError> {
+        let response = self.command(&["LIST", container])?;
+        Ok(split_list(&response))
+    }
+
+    pub fn list_keys(&mut self, container: &str, module: &str) -> Result<Vec<String>, ClientError> {
+        let response = self.command(&["LIST", container, module])?;
+        Ok(split_list(&response))
+    }
+
+    /// Change notifications for containers subscribed to with `SUBSCRIBE`, as
+    /// `EVENT` lines. They are picked up while waiting for the next reply.
+    pub fn take_events(&mut self) -> Vec<String> {
+        self.events.drain(..).collect()
+    }
+
+    /// Sends a command made of whitespace-free arguments and returns the response
+    /// text, turning error responses into `ClientError::Server`.
+    pub fn command(&mut self, arguments: &[&str]) -> Result<String, ClientError> {
+        if let Some(argument) = arguments.iter().find(|argument| argument.is_empty() || argument.contains(char::is_whitespace)) {
+            return Err(ClientError::InvalidArgument(argument.to_string()));
+        }
+        
+        let mut request = arguments.join(" ").into_bytes();
+        request.push(self.delimiter.byte());
+        self.stream.write_all(&request)?;
+        
+        let response = self.read_reply()?;
+        
+        match parse_error(&response) {
+            Some((code, message)) => Err(ClientError::Server {
+                code: code.to_string(),
+                message: message.to_string(),
+            }),
+            None => Ok(response),
+        }
+    }
+
+    /// Reads one framed reply: `<length>` lines each followed by that many bytes and
+    /// a newline, ended by `0`, optionally after a `GZIP` line. EVENT lines can only
+    /// arrive between replies and are set aside for `take_events`.
+    fn read_reply(&mut self) -> Result<String, ClientError> {
+        let mut line = self.read_line()?;
+        while line.starts_with("EVENT ") {
+            self.events.push_back(std::mem::take(&mut line));
+            line = self.read_line()?;
+        }
+        
+        let compressed = line == "GZIP";
+        if compressed {
+            line = self.read_line()?;
+        }
+        
+        let mut body = Vec::new();
+        loop {
+            let length: usize = line.parse().map_err(|_| ClientError::Protocol(format!("expected a chunk length, got '{}'", line)))?;
+            if length == 0 {
+                break;
+            }
+            
+            let start = body.len();
+            body.resize(start + length + 1, 0);
+            self.reader.read_exact(&mut body[start..])?;
+            if body.pop() != Some(b'\n') {
+                return Err(ClientError::Protocol("chunk not followed by a newline".to_string()));
+            }
+            
+            line = self.read_line()?;
+        }
+        
+        if compressed {
+            let mut plain = Vec::new();
+            GzDecoder::new(body.as_slice()).read_to_end(&mut plain)?;
+            body = plain;
+        }
+        
+        String::from_utf8(body).map_err(|_| ClientError::Protocol("reply is not valid UTF-8".to_string()))
+    }
+
+    fn read_line(&mut self) -> Result<String, ClientError> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        
+        line.truncate(line.trim_end_matches('\n').len());
+        Ok(line)
+    }
+}
+
+/// Splits `ERROR[<code>]: <message>` into its code and message.
+fn parse_error(response: &str) -> Option<(&str, &str)> {
+    let rest = response.strip_prefix("ERROR[")?;
+    let (code, message) = rest.split_once("]:")?;
+    Some((code, message.trim()))
+}
+
+/// Lists arrive one item per line, as negotiated through HELLO.
+fn split_list(response: &str) -> Vec<String> {
+    if response.is_empty() {
+        return Vec::new();
+    }
+    
+    response.lines().map(|item| item.to_string()).collect()
+}
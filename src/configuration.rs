@@ -2,14 +2,168 @@
 // Stores structured Data in JSON Files and makes it accessible over TCP, written in Rust.
 
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
+use std::net::Ipv6Addr;
 use std::path::Path;
+use std::sync::{OnceLock, RwLock};
+
+/// Current configuration. Every reload leaks the new `Config` so handlers can
+/// keep borrowing it as `&'static` for the rest of their request.
+static CONFIG: RwLock<Option<&'static Config>> = RwLock::new(None);
+
+/// File the configuration was loaded from, re-read by `reload_config`.
+static CONFIG_PATH: OnceLock<String> = OnceLock::new();
+
+/// Settings that are only read at startup; RECONFIG keeps their current values.
+const RESTART_REQUIRED: &[&str] = &[
+    "ip",
+    "port",
+    "worker_threads",
+    "id_field",
+    "case_insensitive_ids",
+    "shards_per_container",
+    "lazy_containers",
+    "strict_startup",
+    "ready_file",
+    "data_dir",
+    "namespace",
+    "coalesce_writes_ms",
+    "backlog",
+    "reuse_address",
+    "expiry_sweep_interval_secs",
+];
+
+pub const DEFAULT_CONFIG_PATH: &str = "triangular-db.toml";
+
+/// Prefix of environment variables that override settings, e.g. `TRIANGULAR_PORT`.
+const ENV_PREFIX: &str = "TRIANGULAR_";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageFormat {
+    /// One JSON array per container file.
+    Json,
+    /// One module per line in a `.jsonl` file.
+    Jsonl,
+}
+
+/// What INIT does when the container already has a module with the requested id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InitMode {
+    /// Reject the INIT and keep the existing module.
+    Error,
+    /// Overwrite the existing module with the new one.
+    Replace,
+    /// Add the new module next to the existing one.
+    Append,
+}
+
+/// Byte that ends each request on a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RequestDelimiter {
+    Newline,
+    /// A NUL byte. Arguments are then only separated by spaces, which lets values
+    /// contain literal newlines.
+    Null,
+}
+
+impl RequestDelimiter {
+    pub fn byte(self) -> u8 {
+        match self {
+            RequestDelimiter::Newline => b'\n',
+            RequestDelimiter::Null => b'\0',
+        }
+    }
+}
 
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
     pub ip: String,
+    /// Port to listen on, 0 to let the OS pick a free one (reported in the READY line).
     pub port: u16,
     pub silent: bool,
+    /// Name of the field that identifies a module inside a container.
+    pub id_field: String,
+    /// Reject writes to fields that are not part of the container template.
+    pub strict_mode: bool,
+    /// Maximum number of simultaneously open client connections, 0 for unlimited.
+    pub max_connections: usize,
+    /// Connections waiting for a worker thread at which a warning is logged, 0 to never warn.
+    pub queue_depth_warning: usize,
+    /// Indent container files for readability; compact JSON when disabled.
+    pub pretty_storage: bool,
+    /// Maintain an `updated_at` field (epoch milliseconds) on every written module.
+    pub track_timestamps: bool,
+    /// Maintain a `_version` write counter on every written module.
+    pub track_versions: bool,
+    /// Refuse to start when tree.json is malformed instead of starting without containers.
+    pub strict_startup: bool,
+    /// Store container files gzip compressed as `.json.gz`.
+    pub compress_storage: bool,
+    /// Path of the append-only audit log for mutating commands, empty to disable.
+    pub audit_log: String,
+    /// Largest accepted request line in bytes, 0 for unlimited.
+    pub max_request_bytes: usize,
+    /// Match module ids case-insensitively and store new ids in lowercase.
+    pub case_insensitive_ids: bool,
+    /// File written with the bound address once the server accepts connections, empty to disable.
+    pub ready_file: String,
+    /// Commands allowed per second on each connection, 0 to disable rate limiting.
+    pub rate_limit_per_sec: u32,
+    /// Disable Nagle's algorithm on client sockets so small replies are sent immediately.
+    pub tcp_nodelay: bool,
+    /// Enable SO_KEEPALIVE on client sockets to detect dead peers.
+    pub tcp_keepalive: bool,
+    /// Split each container into this many shard files by module id, 0 or 1 for a single file.
+    pub shards_per_container: usize,
+    /// Threads serving connections and creating containers at startup, 0 for one per CPU.
+    pub worker_threads: usize,
+    /// Create container files on first INIT instead of for every container at startup.
+    pub lazy_containers: bool,
+    /// Most modules a single container may hold, 0 for unlimited.
+    pub max_modules_per_container: usize,
+    /// Seconds between sweeps that delete expired modules. The default of 0 only hides
+    /// them on read, as a sweep reads every container.
+    pub expiry_sweep_interval_secs: u64,
+    /// Close connections that send no complete request for this many seconds, 0 to keep them open.
+    pub idle_timeout_secs: u64,
+    /// Flush every storage write to disk with fsync before reporting success. Survives
+    /// power loss at the cost of write throughput, often by an order of magnitude.
+    pub fsync_on_write: bool,
+    /// Directory holding tree.json and the tree/ container directory.
+    pub data_dir: String,
+    /// Layout of container files; `jsonl` stores one module per line so INIT appends
+    /// instead of rewriting. Sharded containers and `compress_storage` keep using `json`.
+    pub storage_format: StorageFormat,
+    /// Keep every container array sorted by module id, so stored files diff cleanly.
+    pub keep_sorted: bool,
+    /// Responses larger than this many bytes are gzipped for connections that sent COMPRESS ON.
+    pub compression_threshold_bytes: usize,
+    /// Merge container writes arriving within this many milliseconds into one file
+    /// rewrite; each command still replies only once its write is on disk. 0 to disable.
+    pub coalesce_writes_ms: u64,
+    /// Prefix of this server's container files and tree.json keys, stored as
+    /// `<namespace>_<container>`, so several namespaces can share one data_dir.
+    pub namespace: String,
+    /// Print a line per command with the connection id, command name and result status.
+    pub log_commands: bool,
+    /// Handling of INIT for an id that already exists: `error`, `replace` or `append`.
+    pub init_mode: InitMode,
+    /// How requests are terminated, `newline` or `null`; applies to new connections.
+    pub request_delimiter: RequestDelimiter,
+    /// Pending connections the listener queues before refusing new ones.
+    pub backlog: u32,
+    /// Set SO_REUSEADDR on the listener so a restarted server can bind again at once.
+    pub reuse_address: bool,
+    /// Commands each AUTH token may run, where `*` allows every command and `@read`
+    /// the read-only ones. Empty to let every connection run everything without AUTH.
+    pub acl: HashMap<String, Vec<String>>,
+    /// JSON Schema file per container that written modules must satisfy, loaded at startup.
+    pub schemas: HashMap<String, String>,
 }
 
 impl Default for Config {
@@ -18,13 +172,53 @@ impl Default for Config {
             ip: "0.0.0.0".to_string(),
             port: 8080,
             silent: false,
+            id_field: "id".to_string(),
+            strict_mode: false,
+            max_connections: 1024,
+            queue_depth_warning: 0,
+            pretty_storage: true,
+            track_timestamps: true,
+            track_versions: true,
+            strict_startup: true,
+            compress_storage: false,
+            audit_log: String::new(),
+            max_request_bytes: 4 * 1024 * 1024,
+            case_insensitive_ids: false,
+            ready_file: String::new(),
+            rate_limit_per_sec: 0,
+            tcp_nodelay: true,
+            tcp_keepalive: false,
+            shards_per_container: 1,
+            worker_threads: 0,
+            lazy_containers: false,
+            max_modules_per_container: 0,
+            expiry_sweep_interval_secs: 0,
+            idle_timeout_secs: 0,
+            fsync_on_write: false,
+            data_dir: ".".to_string(),
+            storage_format: StorageFormat::Json,
+            keep_sorted: false,
+            compression_threshold_bytes: 1024,
+            coalesce_writes_ms: 0,
+            namespace: String::new(),
+            log_commands: false,
+            init_mode: InitMode::Error,
+            request_delimiter: RequestDelimiter::Newline,
+            backlog: 1024,
+            reuse_address: true,
+            acl: HashMap::new(),
+            schemas: HashMap::new(),
         }
     }
 }
 
 impl Config {
     pub fn load_or_create() -> Result<Config, Box<dyn std::error::Error>> {
-        let config_path = "triangular-db.toml";
+        Self::load_or_create_from(DEFAULT_CONFIG_PATH)
+    }
+    
+    pub fn load_or_create_from(config_path: &str) -> Result<Config, Box<dyn std::error::Error>> {
+        CONFIG_PATH.get_or_init(|| config_path.to_string());
         
         let config = if Path::new(config_path).exists() {
             let content = fs::read_to_string(config_path)?;
@@ -33,14 +227,13 @@ impl Config {
                     if config.ip.is_empty() {
                         config.ip = "0.0.0.0".to_string();
                     }
-                    if config.port == 0 {
-                        config.port = 8080;
+                    if config.id_field.is_empty() {
+                        config.id_field = "id".to_string();
                     }
                     config
                 }
-                Err(_) => {
-                    Config::default()
-                }
+                // Never fall back to defaults here: they would be written over the file below
+                Err(e) => return Err(format!("Invalid {}: {}", config_path, e).into()),
             }
         } else {
             Config::default()
@@ -49,10 +242,142 @@ impl Config {
         let toml_string = toml::to_string_pretty(&config)?;
         fs::write(config_path, toml_string)?;
         
-        Ok(config)
+        // Applied after writing so overrides never end up in the file
+        Self::apply_env_overrides(config)
+    }
+    
+    /// Replaces any scalar setting for which a `TRIANGULAR_<SETTING>` variable is set.
+    fn apply_env_overrides(config: Config) -> Result<Config, Box<dyn std::error::Error>> {
+        let toml::Value::Table(mut table) = toml::Value::try_from(&config)? else {
+            return Ok(config);
+        };
+        
+        let mut overridden = false;
+        
+        for (key, value) in table.iter_mut() {
+            let variable = format!("{}{}", ENV_PREFIX, key.to_uppercase());
+            let Ok(raw) = std::env::var(&variable) else {
+                continue;
+            };
+            
+            *value = match value {
+                toml::Value::String(_) => toml::Value::String(raw),
+                toml::Value::Integer(_) => match raw.trim().parse() {
+                    Ok(number) => toml::Value::Integer(number),
+                    Err(_) => return Err(format!("{} must be an integer", variable).into()),
+                },
+                toml::Value::Boolean(_) => match raw.trim().to_lowercase().as_str() {
+                    "true" | "1" => toml::Value::Boolean(true),
+                    "false" | "0" => toml::Value::Boolean(false),
+                    _ => return Err(format!("{} must be true or false", variable).into()),
+                },
+                _ => continue,
+            };
+            
+            overridden = true;
+        }
+        
+        if !overridden {
+            return Ok(config);
+        }
+        
+        Ok(toml::Value::Table(table).try_into()?)
+    }
+    
+    /// Configured worker thread count, resolving 0 to the number of CPUs.
+    pub fn worker_threads(&self) -> usize {
+        if self.worker_threads == 0 {
+            num_cpus::get()
+        } else {
+            self.worker_threads
+        }
     }
     
     pub fn address(&self) -> String {
-        format!("{}:{}", self.ip, self.port)
+        // IPv6 literals need brackets to be told apart from the port separator
+        if self.ip.parse::<Ipv6Addr>().is_ok() {
+            format!("[{}]:{}", self.ip, self.port)
+        } else {
+            format!("{}:{}", self.ip, self.port)
+        }
+    }
+}
+
+/// Installs the loaded configuration, unless one is already in place.
+pub fn set_config(config: Config) -> &'static Config {
+    let mut current = CONFIG.write().unwrap();
+    current.get_or_insert_with(|| Box::leak(Box::new(config)))
+}
+
+pub fn get_config() -> &'static Config {
+    if let Some(config) = *CONFIG.read().unwrap() {
+        return config;
+    }
+    
+    set_config(Config::default())
+}
+
+/// Re-reads the configuration file and installs the settings that can change at
+/// runtime. Returns the changed settings that only take effect after a restart.
+pub fn reload_config() -> Result<(&'static Config, Vec<String>), Box<dyn std::error::Error>> {
+    let path = CONFIG_PATH.get().map(String::as_str).unwrap_or(DEFAULT_CONFIG_PATH);
+    let content = fs::read_to_string(path)?;
+    // The full toml error spans several lines, which the line protocol cannot carry
+    let parsed = toml::from_str::<Config>(&content).map_err(|e| e.message().replace('\n', " "))?;
+    let reloaded = Config::apply_env_overrides(parsed)?;
+    
+    let mut current = CONFIG.write().unwrap();
+    let previous = current.unwrap_or_else(|| Box::leak(Box::default()));
+    
+    let (toml::Value::Table(mut table), toml::Value::Table(old_table)) = (toml::Value::try_from(&reloaded)?, toml::Value::try_from(previous)?) else {
+        return Err("Configuration is not a table".into());
+    };
+    
+    let mut pending = Vec::new();
+    for key in RESTART_REQUIRED {
+        if let Some(old_value) = old_table.get(*key)
+            && table.get(*key) != Some(old_value)
+        {
+            pending.push(key.to_string());
+            table.insert(key.to_string(), old_value.clone());
+        }
     }
-} 
\ No newline at end of file
+    
+    let config: &'static Config = Box::leak(Box::new(toml::Value::Table(table).try_into()?));
+    *current = Some(config);
+    
+    Ok((config, pending))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+
+    fn address_of(ip: &str) -> String {
+        Config { ip: ip.to_string(), port: 8080, ..Config::default() }.address()
+    }
+
+    #[test]
+    fn address_joins_ipv4_and_port() {
+        assert_eq!(address_of("127.0.0.1"), "127.0.0.1:8080");
+        assert!(address_of("0.0.0.0").parse::<SocketAddr>().is_ok());
+    }
+
+    #[test]
+    fn address_brackets_ipv6() {
+        assert_eq!(address_of("::1"), "[::1]:8080");
+        assert_eq!(address_of("fe80::1"), "[fe80::1]:8080");
+        assert!(address_of("::").parse::<SocketAddr>().is_ok());
+    }
+
+    #[test]
+    fn address_leaves_hostnames_alone() {
+        assert_eq!(address_of("localhost"), "localhost:8080");
+    }
+
+    #[test]
+    fn expiry_sweeper_is_off_by_default() {
+        assert_eq!(Config::default().expiry_sweep_interval_secs, 0);
+    }
+}
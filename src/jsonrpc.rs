@@ -0,0 +1,111 @@
+// Copyright (c) 2025, TheByteSlayer, Triangular
+// Stores structured Data in JSON Files and makes it accessible over TCP, written in Rust.
+
+use serde_json::{Value, json};
+use crate::response::{ErrorCode, Response};
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// Any other failure reported by a command; the error code is carried in `data`.
+const SERVER_ERROR: i64 = -32000;
+
+/// Answers one line of JSON-RPC 2.0, a single call or a batch. Each call becomes a
+/// command line of the method name followed by its positional params, which
+/// `execute` runs. Returns `None` when the line only held notifications.
+pub fn handle(line: &str, mut execute: impl FnMut(&str) -> Response) -> Option<String> {
+    let reply = match serde_json::from_str::<Value>(line) {
+        Err(e) => Some(error_reply(Value::Null, PARSE_ERROR, &format!("Parse error: {}", e), None)),
+        Ok(Value::Array(calls)) if calls.is_empty() => Some(error_reply(Value::Null, INVALID_REQUEST, "Empty batch", None)),
+        Ok(Value::Array(calls)) => {
+            let replies: Vec<Value> = calls.into_iter().filter_map(|call| handle_call(call, &mut execute)).collect();
+            (!replies.is_empty()).then_some(Value::Array(replies))
+        }
+        Ok(call) => handle_call(call, &mut execute),
+    };
+    
+    reply.map(|reply| reply.to_string())
+}
+
+fn handle_call(call: Value, execute: &mut impl FnMut(&str) -> Response) -> Option<Value> {
+    let Value::Object(mut call) = call else {
+        return Some(error_reply(Value::Null, INVALID_REQUEST, "Invalid request", None));
+    };
+    
+    // Without an id the call is a notification, which gets no reply at all
+    let id = call.remove("id");
+    if id.as_ref().is_some_and(|id| !(id.is_string() || id.is_number() || id.is_null())) {
+        return Some(error_reply(Value::Null, INVALID_REQUEST, "Invalid request id", None));
+    }
+    let reply_id = id.clone().unwrap_or(Value::Null);
+    
+    let method = match (call.get("jsonrpc").and_then(Value::as_str), call.get("method").and_then(Value::as_str)) {
+        (Some("2.0"), Some(method)) if !method.is_empty() && !method.contains(char::is_whitespace) => method.to_uppercase(),
+        _ => return Some(error_reply(reply_id, INVALID_REQUEST, "Invalid request", None)),
+    };
+    
+    let command = match command_line(&method, call.get("params")) {
+        Ok(command) => command,
+        Err(message) => return id.map(|_| error_reply(reply_id, INVALID_PARAMS, &message, None)),
+    };
+    
+    let response = execute(&command);
+    let id = id?;
+    
+    Some(match response {
+        Response::Ok(message) => json!({ "jsonrpc": "2.0", "result": message, "id": id }),
+        Response::Json(value) => json!({ "jsonrpc": "2.0", "result": value, "id": id }),
+        Response::JsonStream(stream) => match stream.to_value() {
+            Ok(value) => json!({ "jsonrpc": "2.0", "result": value, "id": id }),
+            Err(e) => error_reply(id, SERVER_ERROR, &e.to_string(), None),
+        },
+        Response::List(items) | Response::Lines(items) => json!({ "jsonrpc": "2.0", "result": items, "id": id }),
+        Response::Error(code, message) => {
+            let rpc_code = match code {
+                ErrorCode::UnknownCommand => METHOD_NOT_FOUND,
+                ErrorCode::InvalidArguments | ErrorCode::InvalidName => INVALID_PARAMS,
+                _ => SERVER_ERROR,
+            };
+            error_reply(id, rpc_code, &message, Some(code))
+        }
+    })
+}
+
+/// Joins the method and its params into a command line. Strings are passed as
+/// they are, other values as JSON; only the last param may contain whitespace,
+/// as commands read their payload from the rest of the line.
+fn command_line(method: &str, params: Option<&Value>) -> Result<String, String> {
+    let params = match params {
+        None => &Vec::new(),
+        Some(Value::Array(params)) => params,
+        Some(_) => return Err("Params must be an array".to_string()),
+    };
+    
+    let mut command = method.to_string();
+    for (position, param) in params.iter().enumerate() {
+        let argument = match param {
+            Value::String(text) => text.clone(),
+            other => other.to_string(),
+        };
+        
+        let last = position + 1 == params.len();
+        if argument.is_empty() || (!last && argument.contains(char::is_whitespace)) {
+            return Err(format!("Param {} must be non-empty and free of whitespace", position));
+        }
+        
+        command.push(' ');
+        command.push_str(&argument);
+    }
+    
+    Ok(command)
+}
+
+fn error_reply(id: Value, code: i64, message: &str, error_code: Option<ErrorCode>) -> Value {
+    let mut error = json!({ "code": code, "message": message });
+    if let Some(error_code) = error_code {
+        error["data"] = json!({ "code": error_code.as_str() });
+    }
+    
+    json!({ "jsonrpc": "2.0", "error": error, "id": id })
+}
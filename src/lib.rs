@@ -0,0 +1,13 @@
+// Copyright (c) 2025, TheByteSlayer, Triangular
+// Stores structured Data in JSON Files and makes it accessible over TCP, written in Rust.
+
+pub mod api;
+mod audit;
+pub mod configuration;
+mod jsonrpc;
+mod metrics;
+pub mod response;
+pub mod tree;
+
+#[cfg(feature = "client")]
+pub mod client;
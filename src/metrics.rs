@@ -0,0 +1,74 @@
+// Copyright (c) 2025, TheByteSlayer, Triangular
+// Stores structured Data in JSON Files and makes it accessible over TCP, written in Rust.
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use crate::api::COMMANDS;
+use crate::response::Response;
+
+static REQUESTS_TOTAL: AtomicU64 = AtomicU64::new(0);
+static ERRORS_TOTAL: AtomicU64 = AtomicU64::new(0);
+/// Requests per command, indexed like `COMMANDS`.
+static COMMAND_COUNTS: [AtomicU64; COMMANDS.len()] = [const { AtomicU64::new(0) }; COMMANDS.len()];
+
+/// Counts a processed request. Unknown commands only count towards the totals.
+pub fn record(command: &str, response: &Response) {
+    REQUESTS_TOTAL.fetch_add(1, Ordering::Relaxed);
+    
+    if matches!(response, Response::Error(..)) {
+        ERRORS_TOTAL.fetch_add(1, Ordering::Relaxed);
+    }
+    
+    if let Some(position) = COMMANDS.iter().position(|known| known.name == command) {
+        COMMAND_COUNTS[position].fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Counters as `name value` lines, listing only commands that were used.
+pub fn render_stats(active_connections: usize, queue_depth: usize) -> String {
+    let mut output = format!(
+        "requests_total {}\nerrors_total {}\nactive_connections {}\nqueue_depth {}",
+        REQUESTS_TOTAL.load(Ordering::Relaxed),
+        ERRORS_TOTAL.load(Ordering::Relaxed),
+        active_connections,
+        queue_depth,
+    );
+    
+    for (command, count) in command_counts().filter(|(_, count)| *count > 0) {
+        let _ = write!(output, "\ncommand_{} {}", command.to_lowercase(), count);
+    }
+    
+    output
+}
+
+/// Counters in the Prometheus text exposition format.
+pub fn render_prometheus(active_connections: usize, queue_depth: usize) -> String {
+    let mut output = String::new();
+    
+    let _ = writeln!(output, "# HELP triangular_requests_total Requests processed.");
+    let _ = writeln!(output, "# TYPE triangular_requests_total counter");
+    let _ = writeln!(output, "triangular_requests_total {}", REQUESTS_TOTAL.load(Ordering::Relaxed));
+    let _ = writeln!(output, "# HELP triangular_errors_total Requests answered with an error.");
+    let _ = writeln!(output, "# TYPE triangular_errors_total counter");
+    let _ = writeln!(output, "triangular_errors_total {}", ERRORS_TOTAL.load(Ordering::Relaxed));
+    let _ = writeln!(output, "# HELP triangular_active_connections Open client connections.");
+    let _ = writeln!(output, "# TYPE triangular_active_connections gauge");
+    let _ = writeln!(output, "triangular_active_connections {}", active_connections);
+    let _ = writeln!(output, "# HELP triangular_queue_depth Connections waiting for a worker thread.");
+    let _ = writeln!(output, "# TYPE triangular_queue_depth gauge");
+    let _ = writeln!(output, "triangular_queue_depth {}", queue_depth);
+    let _ = writeln!(output, "# HELP triangular_command_requests_total Requests per command.");
+    let _ = writeln!(output, "# TYPE triangular_command_requests_total counter");
+    
+    for (command, count) in command_counts() {
+        let _ = writeln!(output, "triangular_command_requests_total{{command=\"{}\"}} {}", command, count);
+    }
+    
+    output
+}
+
+fn command_counts() -> impl Iterator<Item = (&'static str, u64)> {
+    COMMANDS.iter()
+        .zip(COMMAND_COUNTS.iter())
+        .map(|(command, count)| (command.name, count.load(Ordering::Relaxed)))
+}
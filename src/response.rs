@@ -0,0 +1,330 @@
+// Copyright (c) 2025, TheByteSlayer, Triangular
+// Stores structured Data in JSON Files and makes it accessible over TCP, written in Rust.
+
+use std::fmt;
+use std::io::{self, BufWriter, Write};
+use std::sync::Arc;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+
+/// Largest chunk a framed reply is split into, so no reply is held whole.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Stable identifier of an error, sent alongside the message so clients can
+/// match on it while the wording stays free to change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// Missing, extra or malformed command arguments.
+    InvalidArguments,
+    /// A container name that is empty, reserved or would point outside the data directory.
+    InvalidName,
+    UnknownCommand,
+    ContainerNotFound,
+    ModuleNotFound,
+    KeyNotFound,
+    TargetExists,
+    ContainerFull,
+    NotANumber,
+    OutOfRange,
+    CasMismatch,
+    SchemaViolation,
+    /// A field that the template or the id field rules do not allow.
+    FieldNotAllowed,
+    /// A value or payload sent by the client that cannot be stored.
+    InvalidData,
+    /// A value that cannot take the fields or options the command gave for it.
+    InvalidValue,
+    /// A file on disk that cannot be parsed or has an unexpected shape.
+    CorruptData,
+    /// Reading or writing a file failed.
+    StorageError,
+    RequestTooLarge,
+    RateLimited,
+    TooManyConnections,
+    NotSubscribed,
+    /// LOCK gave up waiting for another connection to release the lock.
+    LockTimeout,
+    /// UNLOCK of a lock the connection does not hold.
+    NotLockOwner,
+    /// EXEC found a watched module changed since WATCH.
+    TransactionAborted,
+    /// The client asked for a protocol version this server cannot speak.
+    UnsupportedProtocol,
+    /// The connection's AUTH token does not allow the command, or it sent none.
+    Forbidden,
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::InvalidArguments => "INVALID_ARGUMENTS",
+            ErrorCode::InvalidName => "INVALID_NAME",
+            ErrorCode::UnknownCommand => "UNKNOWN_COMMAND",
+            ErrorCode::ContainerNotFound => "CONTAINER_NOT_FOUND",
+            ErrorCode::ModuleNotFound => "MODULE_NOT_FOUND",
+            ErrorCode::KeyNotFound => "KEY_NOT_FOUND",
+            ErrorCode::TargetExists => "TARGET_EXISTS",
+            ErrorCode::ContainerFull => "CONTAINER_FULL",
+            ErrorCode::NotANumber => "NOT_A_NUMBER",
+            ErrorCode::OutOfRange => "OUT_OF_RANGE",
+            ErrorCode::CasMismatch => "CAS_MISMATCH",
+            ErrorCode::SchemaViolation => "SCHEMA_VIOLATION",
+            ErrorCode::FieldNotAllowed => "FIELD_NOT_ALLOWED",
+            ErrorCode::InvalidData => "INVALID_DATA",
+            ErrorCode::InvalidValue => "INVALID_VALUE",
+            ErrorCode::CorruptData => "CORRUPT_DATA",
+            ErrorCode::StorageError => "STORAGE_ERROR",
+            ErrorCode::RequestTooLarge => "REQUEST_TOO_LARGE",
+            ErrorCode::RateLimited => "RATE_LIMITED",
+            ErrorCode::TooManyConnections => "TOO_MANY_CONNECTIONS",
+            ErrorCode::NotSubscribed => "NOT_SUBSCRIBED",
+            ErrorCode::LockTimeout => "LOCK_TIMEOUT",
+            ErrorCode::NotLockOwner => "NOT_LOCK_OWNER",
+            ErrorCode::TransactionAborted => "TRANSACTION_ABORTED",
+            ErrorCode::UnsupportedProtocol => "UNSUPPORTED_PROTOCOL",
+            ErrorCode::Forbidden => "FORBIDDEN",
+            ErrorCode::Internal => "INTERNAL",
+        }
+    }
+}
+
+/// Outcome of a single command, kept separate from how it is written to the client.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Response {
+    Ok(String),
+    Error(ErrorCode, String),
+    /// A JSON document that is serialized straight onto the connection, so large
+    /// dumps are never held in memory as one string.
+    Json(serde_json::Value),
+    /// A JSON document that is produced while it is being written, for replies
+    /// too large to hold as one `Value`.
+    JsonStream(JsonStream),
+    /// Several items such as module ids, joined with `, ` unless the connection
+    /// negotiated one item per line.
+    List(Vec<String>),
+    /// A list sent one item per line, see `into_line_list`.
+    Lines(Vec<String>),
+}
+
+/// Writes a JSON document piece by piece. A failure after the first byte can no
+/// longer become an error reply, so it is returned as an I/O error instead.
+#[derive(Clone)]
+pub struct JsonStream(Arc<WriteDocument>);
+
+type WriteDocument = dyn Fn(&mut dyn Write) -> io::Result<()> + Send + Sync;
+
+impl JsonStream {
+    pub fn new(write: impl Fn(&mut dyn Write) -> io::Result<()> + Send + Sync + 'static) -> Self {
+        Self(Arc::new(write))
+    }
+    
+    pub fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        (self.0)(writer)
+    }
+    
+    /// Builds the whole document, for callers that need it as a `Value`.
+    pub fn to_value(&self) -> io::Result<serde_json::Value> {
+        let mut document = Vec::new();
+        self.write_to(&mut document)?;
+        serde_json::from_slice(&document).map_err(io::Error::other)
+    }
+}
+
+impl fmt::Debug for JsonStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("JsonStream")
+    }
+}
+
+impl PartialEq for JsonStream {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Response {
+    /// Formats the response for the plain text TCP protocol. Errors are sent as
+    /// `ERROR[<CODE>]: <message>`.
+    pub fn to_wire(&self) -> String {
+        match self {
+            Response::Ok(message) => message.clone(),
+            Response::Error(code, message) => format!("ERROR[{}]: {}", code.as_str(), message),
+            Response::Json(value) => value.to_string(),
+            Response::JsonStream(stream) => {
+                let mut document = Vec::new();
+                match stream.write_to(&mut document) {
+                    Ok(()) => String::from_utf8_lossy(&document).into_owned(),
+                    Err(e) => e.to_string(),
+                }
+            }
+            Response::List(items) => items.join(", "),
+            Response::Lines(items) => items.join("\n"),
+        }
+    }
+    
+    /// Puts each item of a list on its own line, which stays unambiguous when an
+    /// item contains a comma. Other responses are returned unchanged.
+    pub fn into_line_list(self) -> Response {
+        match self {
+            Response::List(items) => Response::Lines(items),
+            response => response,
+        }
+    }
+    
+    /// Writes the wire form of the response, streaming JSON documents and lists
+    /// incrementally.
+    pub fn write_to(&self, writer: &mut (impl Write + ?Sized)) -> io::Result<()> {
+        let mut writer = BufWriter::new(writer);
+        
+        match self {
+            Response::Json(value) => serde_json::to_writer(&mut writer, value)?,
+            Response::JsonStream(stream) => stream.write_to(&mut writer)?,
+            Response::List(items) => write_items(&mut writer, items, ", ")?,
+            Response::Lines(items) => write_items(&mut writer, items, "\n")?,
+            Response::Ok(_) | Response::Error(..) => writer.write_all(self.to_wire().as_bytes())?,
+        }
+        
+        writer.flush()
+    }
+    
+    /// Writes the response as a framed reply: chunks of a `<length>` line, that many
+    /// bytes and a newline, ended by a `0` line. With a `compress_threshold`, a reply
+    /// longer than that is gzip compressed and starts with a `GZIP` line instead.
+    pub fn write_framed_to(&self, writer: &mut (impl Write + ?Sized), compress_threshold: Option<usize>) -> io::Result<()> {
+        let mut chunks = ChunkedWriter::new(writer);
+        
+        match compress_threshold {
+            Some(threshold) => {
+                let mut body = ThresholdGzip::new(&mut chunks, threshold, |chunks| chunks.get_mut().write_all(b"GZIP\n"));
+                self.write_to(&mut body)?;
+                body.finish()?;
+            }
+            None => self.write_to(&mut chunks)?,
+        }
+        
+        chunks.finish()
+    }
+}
+
+fn write_items(writer: &mut impl Write, items: &[String], separator: &str) -> io::Result<()> {
+    for (index, item) in items.iter().enumerate() {
+        if index > 0 {
+            writer.write_all(separator.as_bytes())?;
+        }
+        writer.write_all(item.as_bytes())?;
+    }
+    
+    Ok(())
+}
+
+/// Cuts everything written to it into `<length>\n<bytes>\n` chunks.
+struct ChunkedWriter<W: Write> {
+    inner: W,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> ChunkedWriter<W> {
+    fn new(inner: W) -> Self {
+        Self { inner, buffer: Vec::new() }
+    }
+    
+    /// The underlying writer, for lines that precede the first chunk.
+    fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+    
+    fn write_chunk(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        
+        writeln!(self.inner, "{}", self.buffer.len())?;
+        self.inner.write_all(&self.buffer)?;
+        self.inner.write_all(b"\n")?;
+        self.buffer.clear();
+        Ok(())
+    }
+    
+    /// Writes the last chunk and the `0` line that ends the reply.
+    fn finish(mut self) -> io::Result<()> {
+        self.write_chunk()?;
+        self.inner.write_all(b"0\n")?;
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Write for ChunkedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let accepted = buf.len().min(CHUNK_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..accepted]);
+        
+        if self.buffer.len() == CHUNK_SIZE {
+            self.write_chunk()?;
+        }
+        
+        Ok(accepted)
+    }
+    
+    // Chunks are only cut when full or by `finish`, never on flush
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Holds back the first `threshold` bytes of a reply. A reply that fits is passed
+/// on unchanged by `finish`; once it grows past the threshold `on_compress` runs
+/// and everything from the first byte on is gzip compressed into the writer.
+struct ThresholdGzip<W: Write, F: FnOnce(&mut W) -> io::Result<()>> {
+    threshold: usize,
+    held: Vec<u8>,
+    body: Option<GzipBody<W, F>>,
+}
+
+enum GzipBody<W: Write, F> {
+    Held(W, F),
+    Compressed(GzEncoder<W>),
+}
+
+impl<W: Write, F: FnOnce(&mut W) -> io::Result<()>> ThresholdGzip<W, F> {
+    fn new(writer: W, threshold: usize, on_compress: F) -> Self {
+        Self { threshold, held: Vec::new(), body: Some(GzipBody::Held(writer, on_compress)) }
+    }
+    
+    /// Ends the reply and returns the writer, together with whether the reply was compressed.
+    fn finish(mut self) -> io::Result<(W, bool)> {
+        match self.body.take() {
+            Some(GzipBody::Held(mut writer, _)) => {
+                writer.write_all(&self.held)?;
+                Ok((writer, false))
+            }
+            Some(GzipBody::Compressed(encoder)) => Ok((encoder.finish()?, true)),
+            None => Err(io::Error::other("reply already failed")),
+        }
+    }
+}
+
+impl<W: Write, F: FnOnce(&mut W) -> io::Result<()>> Write for ThresholdGzip<W, F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(GzipBody::Compressed(encoder)) = self.body.as_mut() {
+            return encoder.write(buf);
+        }
+        
+        self.held.extend_from_slice(buf);
+        if self.held.len() > self.threshold
+            && let Some(GzipBody::Held(mut writer, on_compress)) = self.body.take()
+        {
+            on_compress(&mut writer)?;
+            let mut encoder = GzEncoder::new(writer, Compression::default());
+            encoder.write_all(&std::mem::take(&mut self.held))?;
+            self.body = Some(GzipBody::Compressed(encoder));
+        }
+        
+        Ok(buf.len())
+    }
+    
+    // The compressed stream is completed by `finish`; flushing early would only add sync blocks
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
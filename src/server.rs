@@ -1,33 +1,57 @@
 // Copyright (c) 2025, TheByteSlayer, Triangular
 // Stores structured Data in JSON Files and makes it accessible over TCP, written in Rust.
 
-mod tree;
-mod configuration;
-mod api;
-
+use triangular_database::{api, configuration, tree};
 use tree::initialize_tree;
 use configuration::Config;
 
+/// Exit statuses, so supervisors can tell a bad configuration from a storage or
+/// network problem without parsing stderr.
+const EXIT_CONFIG: i32 = 2;
+const EXIT_STORAGE: i32 = 3;
+const EXIT_SERVER: i32 = 4;
+
 fn main() {
-    let config = Config::load_or_create().unwrap();
+    let config = match config_path_from_args() {
+        Some(path) => Config::load_or_create_from(&path),
+        None => Config::load_or_create(),
+    };
+    let config = match config {
+        Ok(config) => configuration::set_config(config),
+        Err(e) => exit_with_error(EXIT_CONFIG, "Failed to load configuration", e),
+    };
     
     if let Err(e) = initialize_tree() {
-        if !config.silent {
-            eprintln!("Failed to initialize tree: {}", e);
-        }
-        return;
+        exit_with_error(EXIT_STORAGE, "Failed to initialize tree", e);
     }
     
     if let Err(e) = tree::initialize_containers(config.silent) {
-        if !config.silent {
-            eprintln!("Failed to initialize containers: {}", e);
-        }
-        return;
+        exit_with_error(EXIT_STORAGE, "Failed to initialize containers", e);
+    }
+    
+    if let Err(e) = api::start_server(config) {
+        exit_with_error(EXIT_SERVER, "Server error", e);
     }
+}
+
+/// Reports a fatal error on stderr, regardless of `silent`, and exits with `status`.
+fn exit_with_error(status: i32, context: &str, error: Box<dyn std::error::Error>) -> ! {
+    eprintln!("{}: {}", context, error);
+    std::process::exit(status);
+}
+
+/// Reads `--config <path>` (or `--config=<path>`) from the command line.
+fn config_path_from_args() -> Option<String> {
+    let mut args = std::env::args().skip(1);
     
-    if let Err(e) = api::start_server(&config) {
-        if !config.silent {
-            eprintln!("Server error: {}", e);
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            return args.next();
+        }
+        if let Some(path) = arg.strip_prefix("--config=") {
+            return Some(path.to_string());
         }
     }
-} 
\ No newline at end of file
+    
+    None
+}
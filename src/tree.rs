@@ -2,68 +2,599 @@
 // Stores structured Data in JSON Files and makes it accessible over TCP, written in Rust.
 
 use std::fs;
+use std::io::{self, Read, Write};
 use std::path::Path;
 use std::thread;
 use std::sync::OnceLock;
-use std::sync::{Arc, Mutex};
-use std::collections::HashMap;
+use std::sync::{mpsc, Arc, Condvar, Mutex, MutexGuard, RwLock};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::RangeInclusive;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use crate::configuration::{self, InitMode, StorageFormat};
+use crate::response::{ErrorCode, JsonStream, Response};
+use base64::Engine;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use base64::engine::general_purpose::STANDARD as BASE64;
 
 static CONTAINER_MANAGER: OnceLock<ContainerManager> = OnceLock::new();
 
+/// Counter that keeps temp file names unique between concurrent writes.
+static NEXT_TEMP_FILE: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    /// Set while a DRYRUN command runs, so handlers validate but never touch the disk.
+    static DRY_RUN: Cell<bool> = const { Cell::new(false) };
+    
+    /// Container writes handed to the write coalescer by the current request, as
+    /// container name and generation, awaited by `await_staged_writes`.
+    static STAGED_WRITES: RefCell<Vec<(String, u64)>> = const { RefCell::new(Vec::new()) };
+}
+
+const BINARY_TAG: &str = "$binary";
+
+/// Placeholder returned for keys that are absent in multi-key reads.
+pub const MISSING_VALUE: &str = "(nil)";
+
+/// Field stamped with the last write time in epoch milliseconds when `track_timestamps` is on.
+pub const UPDATED_AT_FIELD: &str = "updated_at";
+
+/// Field holding the epoch millisecond expiry of modules created with a TTL.
+pub const EXPIRES_AT_FIELD: &str = "expires_at";
+
+/// Field counting the writes to a module, bumped by every command that changes it.
+pub const VERSION_FIELD: &str = "_version";
+
+/// Reserved tree.json key for notes about the file itself, such as its schema
+/// `version` and a `description`. It never names a container.
+const TREE_META_KEY: &str = "_meta";
+
+/// How long the reply to an INIT with an idempotency token is kept for retries.
+const IDEMPOTENCY_TTL: Duration = Duration::from_secs(600);
+
+type Subscriber = (u64, mpsc::Sender<String>);
+
+/// Container, normalized module id and token. Tokens are scoped to the module they
+/// create, so two clients reusing a token for different modules do not collide.
+type IdempotencyKey = (String, String, String);
+
+/// Container snapshots waiting for the write coalescer. Every staged snapshot gets a
+/// generation number so callers can tell when their write has landed.
+#[derive(Default)]
+struct StagedWrites {
+    next_generation: u64,
+    /// Newest unwritten snapshot per container, which reads return instead of the file.
+    pending: HashMap<String, (u64, Arc<Vec<serde_json::Value>>)>,
+    /// Newest generation the coalescer finished writing per container.
+    flushed: HashMap<String, u64>,
+    /// Generations per container whose write failed.
+    failed: HashMap<String, Vec<RangeInclusive<u64>>>,
+}
+
+/// Write versions of one container, as watched by WATCH.
+#[derive(Default)]
+struct ContainerVersions {
+    /// Bumped by writes that may touch any module, such as IMPORT.
+    container: u64,
+    modules: HashMap<String, u64>,
+}
+
+/// Positions of the modules in one container array, by normalized id.
+struct ModuleIndex {
+    /// Length of the array the positions describe; a different length means the
+    /// array changed without the index and it has to be rebuilt.
+    len: usize,
+    positions: HashMap<String, usize>,
+}
+
 pub struct ContainerManager {
     container_locks: Arc<Mutex<HashMap<String, Arc<Mutex<()>>>>>,
+    /// Locks for container metadata files, independent of the module data locks.
+    meta_locks: Mutex<HashMap<String, Arc<Mutex<()>>>>,
+    /// Container templates from tree.json and the templates/ directory. Manual edits
+    /// only take effect after a RELOAD. Templates from files are `None` until first used.
+    templates: RwLock<BTreeMap<String, Option<serde_json::Value>>>,
+    /// Per-container map from module id to its position in the container array.
+    module_indexes: Mutex<HashMap<String, ModuleIndex>>,
+    /// Change notification channels per container, tagged with the subscribing connection.
+    subscribers: Mutex<HashMap<String, Vec<Subscriber>>>,
+    /// Compiled JSON Schemas from the `schemas` setting, keyed by container.
+    schemas: RwLock<HashMap<String, jsonschema::Validator>>,
+    /// Digest of the content last written to each shard of a sharded container.
+    shard_digests: Mutex<HashMap<String, Vec<Option<u64>>>>,
+    /// Client-visible advisory locks from LOCK, mapped to the owning connection.
+    advisory_locks: Mutex<HashMap<String, u64>>,
+    /// Signalled whenever an advisory lock is released.
+    advisory_lock_released: Condvar,
+    /// Replies by container, module id and idempotency token with the time they were
+    /// recorded; `None` while the first request carrying the token still runs.
+    idempotent_replies: Mutex<HashMap<IdempotencyKey, (Instant, Option<Response>)>>,
+    /// Signalled whenever a request with an idempotency token finished.
+    idempotent_reply_recorded: Condvar,
+    versions: Mutex<HashMap<String, ContainerVersions>>,
+    /// Source of version numbers, shared by all containers so a bump always increases.
+    next_version: AtomicU64,
+    staged_writes: Mutex<StagedWrites>,
+    /// Signalled when a write is staged and when the coalescer finished a batch.
+    staged_writes_changed: Condvar,
+    /// Set once the write coalescer runs; container writes are only staged after that.
+    coalesce_window: OnceLock<Duration>,
     thread_pool_size: usize,
 }
 
 impl ContainerManager {
-    pub fn new() -> Self {
-        let thread_pool_size = num_cpus::get();
+    pub fn new(thread_pool_size: usize) -> Self {
         Self {
             container_locks: Arc::new(Mutex::new(HashMap::new())),
+            meta_locks: Mutex::new(HashMap::new()),
+            templates: RwLock::new(BTreeMap::new()),
+            module_indexes: Mutex::new(HashMap::new()),
+            subscribers: Mutex::new(HashMap::new()),
+            schemas: RwLock::new(HashMap::new()),
+            shard_digests: Mutex::new(HashMap::new()),
+            advisory_locks: Mutex::new(HashMap::new()),
+            advisory_lock_released: Condvar::new(),
+            idempotent_replies: Mutex::new(HashMap::new()),
+            idempotent_reply_recorded: Condvar::new(),
+            versions: Mutex::new(HashMap::new()),
+            next_version: AtomicU64::new(1),
+            staged_writes: Mutex::new(StagedWrites::default()),
+            staged_writes_changed: Condvar::new(),
+            coalesce_window: OnceLock::new(),
             thread_pool_size,
         }
     }
 
-    pub fn create_containers(&self, silent: bool) -> Result<(), Box<dyn std::error::Error>> {
-        let tree_dir = "tree";
-        let tree_file = "tree.json";
+    /// Loads the templates from tree.json into the cache and, unless
+    /// `lazy_containers` is set, creates a file for every container that does
+    /// not have one yet. A malformed tree.json is
+    /// an error unless `fallback_to_empty` is set, in which case it is reported
+    /// and treated as if it declared no containers.
+    ///
+    /// Containers can also be declared by a `templates/<container>.json` file, which
+    /// is only parsed once the template is needed. tree.json wins when both exist.
+    /// The reserved `_meta` entry is skipped; its `version` is logged.
+    pub fn create_containers(&self, silent: bool, fallback_to_empty: bool) -> Result<usize, Box<dyn std::error::Error>> {
+        let tree_file = &data_path("tree.json");
         
         let tree_content = fs::read_to_string(tree_file)?;
-        let tree_data: serde_json::Value = serde_json::from_str(&tree_content)?;
-        
-        if let serde_json::Value::Object(root_map) = tree_data {
-            let containers: Vec<String> = root_map.keys().cloned().collect();
-            
-            // Use proper multithreading for container creation
-            let chunk_size = (containers.len() + self.thread_pool_size - 1) / self.thread_pool_size;
-            let chunks: Vec<Vec<String>> = containers.chunks(chunk_size).map(|chunk| chunk.to_vec()).collect();
-            
-            thread::scope(|s| {
-                let handles: Vec<_> = chunks.into_iter().map(|chunk| {
-                    s.spawn(move || {
-                        for container_name in chunk {
-                            let container_file_path = format!("{}/{}.json", tree_dir, container_name);
-                            
-                            if !Path::new(&container_file_path).exists() {
-                                let empty_container = serde_json::to_string_pretty(&serde_json::json!([])).unwrap();
-                                if let Err(_) = fs::write(&container_file_path, empty_container) {
-                                    if !silent {
-                                        eprintln!("Failed to create container: {}", container_name);
-                                    }
-                                }
-                            }
+        
+        let root_map = match parse_tree(tree_file, &tree_content) {
+            Ok(mut root_map) => {
+                if let Some(version) = root_map.remove(TREE_META_KEY).as_ref().and_then(|meta| meta.get("version"))
+                    && !silent
+                {
+                    println!("tree.json schema version {}", value_to_string(version));
+                }
+                namespace_entries(root_map)
+            }
+            Err(e) if fallback_to_empty => {
+                if !silent {
+                    eprintln!("{}, starting without containers", e);
+                }
+                serde_json::Map::new()
+            }
+            Err(e) => return Err(e.into()),
+        };
+        
+        let mut templates: BTreeMap<String, Option<serde_json::Value>> = template_file_containers().into_iter().map(|name| (name, None)).collect();
+        templates.extend(root_map.into_iter().map(|(name, template)| (name, Some(template))));
+        
+        let containers: Vec<String> = templates.keys().cloned().collect();
+        *self.templates.write().unwrap() = templates;
+        
+        // Container files may have been edited by hand since the indexes were built
+        self.module_indexes.lock().unwrap().clear();
+        
+        // With lazy containers the file is created by the first INIT instead
+        if configuration::get_config().lazy_containers {
+            return Ok(containers.len());
+        }
+        
+        // Use proper multithreading for container creation
+        let chunk_size = containers.len().div_ceil(self.thread_pool_size).max(1);
+        let chunks: Vec<Vec<String>> = containers.chunks(chunk_size).map(|chunk| chunk.to_vec()).collect();
+        
+        thread::scope(|s| {
+            let handles: Vec<_> = chunks.into_iter().map(|chunk| {
+                s.spawn(move || {
+                    for container_name in chunk {
+                        if create_empty_container(&container_name).is_err() && !silent {
+                            eprintln!("Failed to create container: {}", container_name);
                         }
-                    })
-                }).collect();
+                    }
+                })
+            }).collect();
+            
+            // Wait for all threads to complete
+            for handle in handles {
+                let _ = handle.join();
+            }
+        });
+        
+        Ok(containers.len())
+    }
+
+    pub fn get_template(&self, container_name: &str) -> Option<serde_json::Value> {
+        match self.templates.read().unwrap().get(container_name) {
+            Some(Some(template)) => return Some(template.clone()),
+            Some(None) => {}
+            None => return None,
+        }
+        
+        let template = read_template_file(container_name)?;
+        if let Some(cached) = self.templates.write().unwrap().get_mut(container_name) {
+            cached.get_or_insert_with(|| template.clone());
+        }
+        
+        Some(template)
+    }
+
+    /// Adds a container template to tree.json and the cache.
+    pub fn register_container(&self, container_name: &str, template: serde_json::Value) -> Result<(), Response> {
+        check_container_name(container_name)?;
+        
+        let tree_file = &data_path("tree.json");
+        
+        // Holding the write lock serializes concurrent updates to tree.json
+        let mut templates = self.templates.write().unwrap();
+        
+        let tree_content = fs::read_to_string(tree_file).map_err(|_| Response::Error(ErrorCode::StorageError, "Failed to read tree.json".to_string()))?;
+        let mut root_map = parse_tree(tree_file, &tree_content).map_err(|e| Response::Error(ErrorCode::CorruptData, e))?;
+        root_map.insert(storage_name(container_name).into_owned(), template.clone());
+        
+        let formatted = serde_json::to_string_pretty(&root_map).map_err(|_| Response::Error(ErrorCode::StorageError, "Failed to format data".to_string()))?;
+        
+        if DRY_RUN.get() {
+            return Ok(());
+        }
+        
+        write_file(tree_file, formatted.as_bytes()).map_err(|_| Response::Error(ErrorCode::StorageError, "Failed to write tree.json".to_string()))?;
+        
+        templates.insert(container_name.to_string(), Some(template));
+        Ok(())
+    }
+
+    pub fn container_names(&self) -> Vec<String> {
+        self.templates.read().unwrap().keys().cloned().collect()
+    }
+
+    /// Looks up the position of a module in a container array through the cached
+    /// index. The index is only rebuilt when it no longer matches the array, never
+    /// because the module is missing, so lookups of absent ids stay cheap.
+    pub fn module_position(&self, container_name: &str, array: &[serde_json::Value], id_field: &str, module: &str) -> Option<usize> {
+        let key = normalize_id(module);
+        let mut indexes = self.module_indexes.lock().unwrap();
+        let index = indexes
+            .entry(container_name.to_string())
+            .or_insert_with(|| build_module_index(array, id_field));
+        
+        if index.len != array.len() {
+            *index = build_module_index(array, id_field);
+        }
+        
+        let position = *index.positions.get(key.as_ref())?;
+        if array.get(position).and_then(|item| module_id(item, id_field)).is_some_and(|id| normalize_id(id) == key) {
+            return Some(position);
+        }
+        
+        // A hit that points at another module means the array was reordered
+        *index = build_module_index(array, id_field);
+        index.positions.get(key.as_ref()).copied()
+    }
+
+    /// Adds a module appended at `position` to the cached index. Any other change
+    /// drops the index so the next lookup rebuilds it.
+    pub fn record_module_position(&self, container_name: &str, module: &str, position: usize) {
+        let mut indexes = self.module_indexes.lock().unwrap();
+        let Some(index) = indexes.get_mut(container_name) else {
+            return;
+        };
+        
+        if index.len == position {
+            index.positions.entry(normalize_id(module).into_owned()).or_insert(position);
+            index.len = position + 1;
+        } else {
+            indexes.remove(container_name);
+        }
+    }
+
+    pub fn invalidate_module_index(&self, container_name: &str) {
+        self.module_indexes.lock().unwrap().remove(container_name);
+    }
+
+    /// Starts a background thread that removes expired modules from every
+    /// container once per `interval`.
+    pub fn start_expiry_sweeper(&'static self, interval: Duration) {
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            
+            for container_name in self.container_names() {
+                self.sweep_expired(&container_name);
+            }
+            
+            let _ = await_staged_writes();
+        });
+    }
+
+    /// Starts the thread that merges container writes arriving within `window` of
+    /// each other into one write per container, see `coalesce_writes_ms`.
+    pub fn start_write_coalescer(&'static self, window: Duration) {
+        if self.coalesce_window.set(window).is_err() {
+            return;
+        }
+        
+        thread::spawn(move || loop {
+            let mut staged = self.staged_writes.lock().unwrap();
+            while staged.pending.is_empty() {
+                staged = self.staged_writes_changed.wait(staged).unwrap();
+            }
+            drop(staged);
+            
+            // Lets concurrent writers join the batch
+            thread::sleep(window);
+            
+            let batch: Vec<(String, u64, Arc<Vec<serde_json::Value>>)> = self.staged_writes.lock().unwrap()
+                .pending.iter()
+                .map(|(container_name, (generation, data))| (container_name.clone(), *generation, Arc::clone(data)))
+                .collect();
+            
+            for (container_name, generation, data) in batch {
+                let result = store_container(&container_name, &data);
+                
+                let mut staged = self.staged_writes.lock().unwrap();
+                let previous = staged.flushed.insert(container_name.clone(), generation).unwrap_or(0);
+                
+                // A failed snapshot is dropped so reads fall back to what is on disk
+                if staged.pending.get(&container_name).is_some_and(|(pending, _)| *pending == generation) {
+                    staged.pending.remove(&container_name);
+                }
+                
+                if result.is_err() {
+                    if !configuration::get_config().silent {
+                        eprintln!("Failed to write coalesced changes to container: {}", container_name);
+                    }
+                    staged.failed.entry(container_name).or_default().push(previous + 1..=generation);
+                }
                 
-                // Wait for all threads to complete
-                for handle in handles {
-                    let _ = handle.join();
+                self.staged_writes_changed.notify_all();
+            }
+        });
+    }
+
+    /// Hands a container snapshot to the write coalescer and returns its generation.
+    fn stage_write(&self, container_name: &str, data: Vec<serde_json::Value>) -> u64 {
+        let mut staged = self.staged_writes.lock().unwrap();
+        staged.next_generation += 1;
+        let generation = staged.next_generation;
+        
+        staged.pending.insert(container_name.to_string(), (generation, Arc::new(data)));
+        self.staged_writes_changed.notify_all();
+        
+        generation
+    }
+
+    fn staged_container(&self, container_name: &str) -> Option<Arc<Vec<serde_json::Value>>> {
+        let staged = self.staged_writes.lock().unwrap();
+        staged.pending.get(container_name).map(|(_, data)| Arc::clone(data))
+    }
+
+    fn sweep_expired(&self, container_name: &str) {
+        let lock = self.get_container_lock(container_name);
+        let _guard = lock.lock().unwrap();
+        
+        if !container_exists(container_name) {
+            return;
+        }
+        
+        let Ok(mut data) = read_container(container_name) else {
+            return;
+        };
+        
+        if purge_expired(&mut data) == 0 {
+            return;
+        }
+        
+        self.invalidate_module_index(container_name);
+        
+        if write_container(container_name, &data).is_err() && !configuration::get_config().silent {
+            eprintln!("Failed to remove expired modules from container: {}", container_name);
+        }
+    }
+
+    pub fn subscribe(&self, container_name: &str, connection_id: u64, sender: mpsc::Sender<String>) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        let container_subscribers = subscribers.entry(container_name.to_string()).or_default();
+        
+        if !container_subscribers.iter().any(|(id, _)| *id == connection_id) {
+            container_subscribers.push((connection_id, sender));
+        }
+    }
+
+    pub fn unsubscribe(&self, container_name: &str, connection_id: u64) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        if let Some(container_subscribers) = subscribers.get_mut(container_name) {
+            container_subscribers.retain(|(id, _)| *id != connection_id);
+        }
+    }
+
+    /// Current version of a module, which changes whenever the module or its whole
+    /// container is written. Modules that were never written report 0.
+    pub fn module_version(&self, container_name: &str, module: &str) -> u64 {
+        let versions = self.versions.lock().unwrap();
+        versions.get(container_name).map_or(0, |container_versions| {
+            let module_version = container_versions.modules.get(normalize_id(module).as_ref()).copied().unwrap_or(0);
+            module_version.max(container_versions.container)
+        })
+    }
+    
+    pub fn bump_module_version(&self, container_name: &str, module: &str) {
+        let version = self.next_version.fetch_add(1, Ordering::SeqCst);
+        let mut versions = self.versions.lock().unwrap();
+        versions.entry(container_name.to_string()).or_default().modules.insert(normalize_id(module).into_owned(), version);
+    }
+    
+    pub fn bump_container_version(&self, container_name: &str) {
+        let version = self.next_version.fetch_add(1, Ordering::SeqCst);
+        let mut versions = self.versions.lock().unwrap();
+        versions.entry(container_name.to_string()).or_default().container = version;
+    }
+    
+    /// Grants the named advisory lock to a connection, waiting up to `timeout` for
+    /// its current owner to release it. A connection that already holds it keeps it.
+    pub fn acquire_advisory_lock(&self, name: &str, owner: u64, timeout: Duration) -> bool {
+        let deadline = std::time::Instant::now() + timeout;
+        let mut locks = self.advisory_locks.lock().unwrap();
+        
+        loop {
+            match locks.get(name) {
+                None => {
+                    locks.insert(name.to_string(), owner);
+                    return true;
+                }
+                Some(&holder) if holder == owner => return true,
+                Some(_) => {}
+            }
+            
+            let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+            if remaining.is_zero() {
+                return false;
+            }
+            
+            locks = self.advisory_lock_released.wait_timeout(locks, remaining).unwrap().0;
+        }
+    }
+    
+    /// Releases an advisory lock, returning false when the connection does not hold it.
+    pub fn release_advisory_lock(&self, name: &str, owner: u64) -> bool {
+        let mut locks = self.advisory_locks.lock().unwrap();
+        if locks.get(name) != Some(&owner) {
+            return false;
+        }
+        
+        locks.remove(name);
+        self.advisory_lock_released.notify_all();
+        true
+    }
+    
+    /// Releases every advisory lock a connection holds, e.g. once it disconnects.
+    pub fn release_advisory_locks(&self, owner: u64) {
+        let mut locks = self.advisory_locks.lock().unwrap();
+        let before = locks.len();
+        locks.retain(|_, holder| *holder != owner);
+        
+        if locks.len() != before {
+            self.advisory_lock_released.notify_all();
+        }
+    }
+
+    /// Claims an idempotency token for a new request creating `module` in
+    /// `container_name`. Returns the recorded reply when the token was used for the
+    /// same module before, waiting for it if that request still runs.
+    pub fn begin_idempotent(&self, container_name: &str, module: &str, token: &str) -> Option<Response> {
+        let key = idempotency_key(container_name, module, token);
+        let mut replies = self.idempotent_replies.lock().unwrap();
+        replies.retain(|_, (recorded, reply)| reply.is_none() || recorded.elapsed() < IDEMPOTENCY_TTL);
+        
+        loop {
+            match replies.get(&key) {
+                Some((_, Some(reply))) => return Some(reply.clone()),
+                Some((_, None)) => replies = self.idempotent_reply_recorded.wait(replies).unwrap(),
+                None => {
+                    replies.insert(key, (Instant::now(), None));
+                    return None;
                 }
+            }
+        }
+    }
+    
+    /// Records the reply for a token claimed by `begin_idempotent`. Errors are not
+    /// kept, so a retry after a failure runs the request again.
+    pub fn finish_idempotent(&self, container_name: &str, module: &str, token: &str, reply: &Response) {
+        let key = idempotency_key(container_name, module, token);
+        let mut replies = self.idempotent_replies.lock().unwrap();
+        
+        if matches!(reply, Response::Error(..)) {
+            replies.remove(&key);
+        } else {
+            replies.insert(key, (Instant::now(), Some(reply.clone())));
+        }
+        
+        self.idempotent_reply_recorded.notify_all();
+    }
+
+    /// Sends a change notification to every subscriber of a container except the
+    /// connection that caused the change. Subscribers that went away are dropped.
+    pub fn publish(&self, container_name: &str, origin: Option<u64>, message: &str) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        if let Some(container_subscribers) = subscribers.get_mut(container_name) {
+            container_subscribers.retain(|(id, sender)| {
+                Some(*id) == origin || sender.send(message.to_string()).is_ok()
             });
         }
+    }
+
+    /// Reads and compiles the schema file configured for each container.
+    pub fn load_schemas(&self, paths: &HashMap<String, String>) -> Result<usize, Box<dyn std::error::Error>> {
+        let mut schemas = HashMap::new();
         
-        Ok(())
+        for (container_name, path) in paths {
+            let content = fs::read_to_string(path)
+                .map_err(|e| format!("Failed to read schema '{}': {}", path, e))?;
+            let schema: serde_json::Value = serde_json::from_str(&content)
+                .map_err(|e| format!("Malformed schema '{}': {}", path, e))?;
+            let validator = jsonschema::validator_for(&schema)
+                .map_err(|e| format!("Invalid schema '{}': {}", path, e))?;
+            
+            schemas.insert(container_name.clone(), validator);
+        }
+        
+        let count = schemas.len();
+        *self.schemas.write().unwrap() = schemas;
+        Ok(count)
+    }
+
+    /// Checks a module against its container's schema, if one is configured. Fields
+    /// the server maintains itself are left out, so schemas with
+    /// `additionalProperties: false` need not list them.
+    pub fn validate_module(&self, container_name: &str, module: &serde_json::Map<String, serde_json::Value>) -> Result<(), Response> {
+        let schemas = self.schemas.read().unwrap();
+        let Some(validator) = schemas.get(container_name) else {
+            return Ok(());
+        };
+        
+        let mut instance = module.clone();
+        for field in maintained_fields() {
+            instance.remove(field);
+        }
+        
+        let instance = serde_json::Value::Object(instance);
+        match validator.validate(&instance) {
+            Ok(()) => Ok(()),
+            Err(e) if e.instance_path().is_empty() => Err(Response::Error(ErrorCode::SchemaViolation, format!("Schema violation: {}", e))),
+            Err(e) => Err(Response::Error(ErrorCode::SchemaViolation, format!("Schema violation: {}: {}", e.instance_path(), e))),
+        }
+    }
+
+    /// Remembers the digest of a shard about to be written and reports whether
+    /// it differs from the previous one, i.e. whether the shard needs rewriting.
+    pub fn update_shard_digest(&self, container_name: &str, shard: usize, digest: u64) -> bool {
+        let mut shard_digests = self.shard_digests.lock().unwrap();
+        let digests = shard_digests.entry(container_name.to_string()).or_default();
+        
+        if digests.len() <= shard {
+            digests.resize(shard + 1, None);
+        }
+        
+        digests[shard].replace(digest) != Some(digest)
+    }
+
+    pub fn forget_shard_digests(&self, container_name: &str) {
+        self.shard_digests.lock().unwrap().remove(container_name);
     }
 
     pub fn get_container_lock(&self, container_name: &str) -> Arc<Mutex<()>> {
@@ -72,22 +603,45 @@ impl ContainerManager {
             .or_insert_with(|| Arc::new(Mutex::new(())))
             .clone()
     }
+
+    /// Locks of several containers in the canonical (sorted) order with duplicates
+    /// removed. Operations spanning containers must lock them through
+    /// `lock_containers` so two of them can never wait on each other.
+    pub fn get_container_locks(&self, container_names: &[&str]) -> Vec<Arc<Mutex<()>>> {
+        let mut names = container_names.to_vec();
+        names.sort_unstable();
+        names.dedup();
+        
+        names.into_iter().map(|container_name| self.get_container_lock(container_name)).collect()
+    }
+
+    pub fn get_meta_lock(&self, container_name: &str) -> Arc<Mutex<()>> {
+        let mut locks = self.meta_locks.lock().unwrap();
+        locks.entry(container_name.to_string())
+            .or_insert_with(|| Arc::new(Mutex::new(())))
+            .clone()
+    }
+}
+
+/// Acquires locks from `get_container_locks` in the order they were returned.
+pub fn lock_containers(locks: &[Arc<Mutex<()>>]) -> Vec<MutexGuard<'_, ()>> {
+    locks.iter().map(|lock| lock.lock().unwrap()).collect()
 }
 
 pub fn get_container_manager() -> &'static ContainerManager {
-    CONTAINER_MANAGER.get_or_init(|| ContainerManager::new())
+    CONTAINER_MANAGER.get_or_init(|| ContainerManager::new(configuration::get_config().worker_threads()))
 }
 
 pub fn initialize_tree() -> Result<(), Box<dyn std::error::Error>> {
-    let tree_dir = "tree";
-    let tree_file = "tree.json";
+    let tree_dir = data_path("tree");
+    let tree_file = data_path("tree.json");
     
-    if !Path::new(tree_dir).exists() {
-        fs::create_dir(tree_dir)?;
+    if !Path::new(&tree_dir).exists() {
+        fs::create_dir_all(&tree_dir)?;
     }
     
-    if !Path::new(tree_file).exists() {
-        fs::write(tree_file, "{}")?;
+    if !Path::new(&tree_file).exists() {
+        fs::write(&tree_file, "{}")?;
     }
     
     Ok(())
@@ -95,278 +649,2351 @@ pub fn initialize_tree() -> Result<(), Box<dyn std::error::Error>> {
 
 pub fn initialize_containers(silent: bool) -> Result<(), Box<dyn std::error::Error>> {
     let manager = get_container_manager();
-    manager.create_containers(silent)?;
+    let config = configuration::get_config();
+    manager.create_containers(silent, !config.strict_startup)?;
+    manager.load_schemas(&config.schemas)?;
+    
+    if config.expiry_sweep_interval_secs > 0 {
+        manager.start_expiry_sweeper(Duration::from_secs(config.expiry_sweep_interval_secs));
+    }
+    
+    if config.coalesce_writes_ms > 0 {
+        manager.start_write_coalescer(Duration::from_millis(config.coalesce_writes_ms));
+    }
+    
+    Ok(())
+}
+
+/// Waits until the container writes staged by this thread's handlers are on disk.
+/// Call it once the container locks are released, so other writers can join the batch.
+pub fn await_staged_writes() -> Result<(), Response> {
+    let tickets = STAGED_WRITES.take();
+    if tickets.is_empty() {
+        return Ok(());
+    }
+    
+    let manager = get_container_manager();
+    let mut staged = manager.staged_writes.lock().unwrap();
+    let mut failed = false;
+    
+    for (container_name, generation) in tickets {
+        while staged.flushed.get(&container_name).is_none_or(|flushed| *flushed < generation) {
+            staged = manager.staged_writes_changed.wait(staged).unwrap();
+        }
+        
+        failed |= staged.failed.get(&container_name).is_some_and(|ranges| ranges.iter().any(|range| range.contains(&generation)));
+    }
+    
+    if failed {
+        return Err(Response::Error(ErrorCode::StorageError, "Failed to write container file".to_string()));
+    }
+    
     Ok(())
 }
 
-pub fn handle_init(container: &str, value: &str) -> String {
+/// Waits until the write coalescer has written every staged container snapshot,
+/// including those no request is waiting for, such as the expiry sweeper's.
+pub fn flush_staged_writes() {
+    let manager = get_container_manager();
+    let mut staged = manager.staged_writes.lock().unwrap();
+    
+    while !staged.pending.is_empty() {
+        staged = manager.staged_writes_changed.wait(staged).unwrap();
+    }
+}
+
+/// Runs `f` with storage writes disabled for handlers called from it.
+pub fn dry_run<R>(f: impl FnOnce() -> R) -> R {
+    struct Reset;
+    
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            DRY_RUN.set(false);
+        }
+    }
+    
+    DRY_RUN.set(true);
+    let _reset = Reset;
+    f()
+}
+
+pub fn handle_reload() -> Response {
+    let manager = get_container_manager();
+    
+    match manager.create_containers(configuration::get_config().silent, false) {
+        Ok(count) => Response::Ok(format!("RELOAD {} containers", count)),
+        Err(e) => Response::Error(ErrorCode::StorageError, format!("Failed to reload tree.json: {}", e)),
+    }
+}
+
+/// Creates a module from the container template. With a TTL the module gets an
+/// `expires_at` stamp and disappears once it passes.
+pub fn handle_init(container: &str, value: &str, fields: &[(&str, &str)], ttl_seconds: Option<u64>) -> Response {
     // Each JSON operation runs in its own thread context with proper locking
     let manager = get_container_manager();
-    let _lock = manager.get_container_lock(container);
+    let lock = manager.get_container_lock(container);
+    let _guard = lock.lock().unwrap();
     
     // Spawn the actual file operations in a separate thread for better parallelism
     let container_name = container.to_string();
-    let value_str = value.to_string();
+    let value_str = normalize_id(value).into_owned();
+    let config = configuration::get_config();
+    let id_field = &config.id_field;
     
-    thread::scope(|s| {
-        s.spawn(|| {
-            let tree_content = match fs::read_to_string("tree.json") {
-                Ok(content) => content,
-                Err(_) => return "ERROR: Failed to read tree.json".to_string(),
-            };
+    run_handler(|| {
+        if let Some(template) = manager.get_template(&container_name) {
+            let mut new_container = template;
             
-            let tree_data: serde_json::Value = match serde_json::from_str(&tree_content) {
-                Ok(data) => data,
-                Err(_) => return "ERROR: Failed to parse tree.json".to_string(),
-            };
+            replace_placeholder(&mut new_container, id_field, &value_str);
             
-            if let Some(template) = tree_data.get(&container_name) {
-                let mut new_container = template.clone();
-                
-                replace_placeholder(&mut new_container, &value_str);
-                
-                let container_file = format!("tree/{}.json", container_name);
-                
-                let mut current_data = if Path::new(&container_file).exists() {
-                    match fs::read_to_string(&container_file) {
-                        Ok(content) => match serde_json::from_str::<serde_json::Value>(&content) {
-                            Ok(data) => data,
-                            Err(_) => serde_json::json!([]),
-                        },
-                        Err(_) => serde_json::json!([]),
+            if !new_container.is_object() && (!fields.is_empty() || ttl_seconds.is_some()) {
+                return Response::Error(ErrorCode::InvalidValue, format!("Template of '{}' is not an object and takes no fields or TTL", container_name));
+            }
+            
+            if let Some(obj) = new_container.as_object_mut() {
+                for (key, field_value) in fields {
+                    if *key == id_field {
+                        return Response::Error(ErrorCode::FieldNotAllowed, format!("Field '{}' is the module id", key));
                     }
-                } else {
-                    serde_json::json!([])
-                };
+                    if let Err(e) = check_writable_key(key) {
+                        return e;
+                    }
+                    if config.strict_mode && !obj.contains_key(*key) {
+                        return Response::Error(ErrorCode::FieldNotAllowed, format!("Field '{}' not in template", key));
+                    }
+                    obj.insert(key.to_string(), serde_json::Value::String(field_value.to_string()));
+                }
                 
-                if let Some(array) = current_data.as_array_mut() {
-                    array.push(new_container);
+                if let Some(ttl_seconds) = ttl_seconds {
+                    let expires_at = now_millis().saturating_add(ttl_seconds.saturating_mul(1000));
+                    obj.insert(EXPIRES_AT_FIELD.to_string(), serde_json::Value::from(expires_at));
                 }
                 
-                let formatted_data = match serde_json::to_string_pretty(&current_data) {
-                    Ok(data) => data,
-                    Err(_) => return "ERROR: Failed to format data".to_string(),
-                };
+                touch_module(obj);
                 
-                if let Err(_) = fs::write(&container_file, formatted_data) {
-                    return "ERROR: Failed to write container file".to_string();
+                if let Err(e) = manager.validate_module(&container_name, obj) {
+                    return e;
                 }
-                
-                format!("INIT {} in Container '{}'", value_str, container_name)
-            } else {
-                "ERROR: Container not found in tree.json".to_string()
             }
-        }).join().unwrap_or_else(|_| "ERROR: Thread panic".to_string())
-    })
-}
-
-pub fn handle_set(container: &str, module: &str, key: &str, value: &str) -> String {
-    let manager = get_container_manager();
-    let _lock = manager.get_container_lock(container);
-    
-    let container_name = container.to_string();
-    let module_name = module.to_string();
-    let key_name = key.to_string();
-    let value_str = value.to_string();
-    
-    thread::scope(|s| {
-        s.spawn(|| {
-            let container_file = format!("tree/{}.json", container_name);
             
-            if !Path::new(&container_file).exists() {
-                return "ERROR: Container does not exist".to_string();
+            let mut current_data = if container_exists(&container_name) {
+                match read_container(&container_name) {
+                    Ok(data) => data,
+                    Err(e) => return e,
+                }
+            } else {
+                Vec::new()
+            };
+            
+            // Expired modules would otherwise shadow a new module with the same id
+            let purged = purge_expired(&mut current_data) > 0;
+            if purged {
+                manager.invalidate_module_index(&container_name);
             }
             
-            let mut current_data = match fs::read_to_string(&container_file) {
-                Ok(content) => match serde_json::from_str::<serde_json::Value>(&content) {
-                    Ok(data) => data,
-                    Err(_) => return "ERROR: Failed to parse container file".to_string(),
-                },
-                Err(_) => return "ERROR: Failed to read container file".to_string(),
+            let replaced = match insert_new_module(&container_name, &mut current_data, &value_str, new_container) {
+                Ok(replaced) => replaced,
+                Err(e) => return e,
             };
             
-            if let Some(array) = current_data.as_array_mut() {
-                for item in array {
-                    if let Some(obj) = item.as_object_mut() {
-                        if obj.get("id").and_then(|v| v.as_str()) == Some(&module_name) {
-                            obj.insert(key_name.clone(), serde_json::Value::String(value_str.clone()));
-                            
-                            let formatted_data = match serde_json::to_string_pretty(&current_data) {
-                                Ok(data) => data,
-                                Err(_) => return "ERROR: Failed to format data".to_string(),
-                            };
-                            
-                            if let Err(_) = fs::write(&container_file, formatted_data) {
-                                return "ERROR: Failed to write container file".to_string();
-                            }
-                            
-                            return format!("SET {} {}", key_name, value_str);
-                        }
-                    }
-                }
+            let written = if purged || replaced {
+                write_container(&container_name, &current_data)
+            } else {
+                write_appended_container(&container_name, &current_data)
+            };
+            if let Err(e) = written {
+                return e;
             }
             
-            "ERROR: Module not found".to_string()
-        }).join().unwrap_or_else(|_| "ERROR: Thread panic".to_string())
+            Response::Ok(format!("INIT {} in Container '{}'", value_str, container_name))
+        } else {
+            Response::Error(ErrorCode::ContainerNotFound, "Container not found in tree.json".to_string())
+        }
     })
 }
 
-pub fn handle_get(container: &str, module: &str, key: &str) -> String {
+/// Adds a module created by INIT or INITJSON, handling an existing module with the
+/// same id as `init_mode` says. Returns whether an existing module was replaced.
+fn insert_new_module(container_name: &str, data: &mut Vec<serde_json::Value>, module_name: &str, module: serde_json::Value) -> Result<bool, Response> {
     let manager = get_container_manager();
-    let _lock = manager.get_container_lock(container);
+    let config = configuration::get_config();
     
-    let container_name = container.to_string();
-    let module_name = module.to_string();
-    let key_name = key.to_string();
+    if config.init_mode != InitMode::Append
+        && let Some(position) = manager.module_position(container_name, data, &config.id_field, module_name)
+    {
+        if config.init_mode == InitMode::Error {
+            return Err(Response::Error(ErrorCode::TargetExists, "Module already exists".to_string()));
+        }
+        
+        // The replacement continues the old module's version so it keeps increasing
+        let mut module = module;
+        if config.track_versions
+            && let (Some(new), Some(old)) = (module.as_object_mut(), data[position].as_object())
+        {
+            let version = module_version_of(old).saturating_add(1);
+            new.insert(VERSION_FIELD.to_string(), serde_json::Value::from(version));
+        }
+        
+        data[position] = module;
+        return Ok(true);
+    }
     
-    thread::scope(|s| {
-        s.spawn(|| {
-            let container_file = format!("tree/{}.json", container_name);
-            
-            if !Path::new(&container_file).exists() {
-                return "ERROR: Container does not exist".to_string();
-            }
-            
-            let content = match fs::read_to_string(&container_file) {
-                Ok(content) => content,
-                Err(_) => return "ERROR: Failed to read container file".to_string(),
-            };
-            
-            let data: serde_json::Value = match serde_json::from_str(&content) {
+    if exceeds_module_limit(data.len() + 1) {
+        return Err(Response::Error(ErrorCode::ContainerFull, "Container full".to_string()));
+    }
+    
+    data.push(module);
+    manager.record_module_position(container_name, module_name, data.len() - 1);
+    Ok(false)
+}
+
+/// Creates a module from a client supplied JSON object instead of the template.
+pub fn handle_init_json(container: &str, payload: &str) -> Response {
+    let manager = get_container_manager();
+    let lock = manager.get_container_lock(container);
+    let _guard = lock.lock().unwrap();
+    
+    let container_name = container.to_string();
+    let config = configuration::get_config();
+    let id_field = &config.id_field;
+    
+    run_handler(|| {
+        let Some(template) = manager.get_template(&container_name) else {
+            return Response::Error(ErrorCode::ContainerNotFound, "Container not found in tree.json".to_string());
+        };
+        
+        let mut module = match serde_json::from_str::<serde_json::Value>(payload) {
+            Ok(serde_json::Value::Object(module)) => module,
+            _ => return Response::Error(ErrorCode::InvalidData, "Module must be a JSON object".to_string()),
+        };
+        
+        let module_name = match module.get(id_field).and_then(|id| id.as_str()) {
+            Some(id) if !id.is_empty() => normalize_id(id).into_owned(),
+            _ => return Response::Error(ErrorCode::InvalidData, format!("Module needs a string '{}' field", id_field)),
+        };
+        module.insert(id_field.clone(), serde_json::Value::String(module_name.clone()));
+        if config.track_versions {
+            module.remove(VERSION_FIELD);
+        }
+        
+        if config.strict_mode
+            && let Some(key) = module.keys().find(|key| template.get(key.as_str()).is_none())
+        {
+            return Response::Error(ErrorCode::FieldNotAllowed, format!("Field '{}' not in template", key));
+        }
+        
+        touch_module(&mut module);
+        
+        if let Err(e) = manager.validate_module(&container_name, &module) {
+            return e;
+        }
+        
+        let mut current_data = if container_exists(&container_name) {
+            match read_container(&container_name) {
                 Ok(data) => data,
-                Err(_) => return "ERROR: Failed to parse container file".to_string(),
-            };
-            
-            if let Some(array) = data.as_array() {
-                for item in array {
-                    if let Some(obj) = item.as_object() {
-                        if obj.get("id").and_then(|v| v.as_str()) == Some(&module_name) {
-                            if let Some(value) = obj.get(&key_name) {
-                                return value.as_str().unwrap_or("").to_string();
-                            }
-                        }
-                    }
-                }
+                Err(e) => return e,
             }
-            
-            "ERROR: Key not found".to_string()
-        }).join().unwrap_or_else(|_| "ERROR: Thread panic".to_string())
+        } else {
+            Vec::new()
+        };
+        
+        let purged = purge_expired(&mut current_data) > 0;
+        if purged {
+            manager.invalidate_module_index(&container_name);
+        }
+        
+        let replaced = match insert_new_module(&container_name, &mut current_data, &module_name, serde_json::Value::Object(module)) {
+            Ok(replaced) => replaced,
+            Err(e) => return e,
+        };
+        
+        let written = if purged || replaced {
+            write_container(&container_name, &current_data)
+        } else {
+            write_appended_container(&container_name, &current_data)
+        };
+        if let Err(e) = written {
+            return e;
+        }
+        
+        Response::Ok(format!("INITJSON {} in Container '{}'", module_name, container_name))
     })
 }
 
-pub fn handle_list_modules(container: &str) -> String {
+/// Appends a JSON array of modules in one write. Each module carries its own id
+/// like with INITJSON; ids already in the container or repeated in the array are
+/// skipped and listed in the reply.
+pub fn handle_bulk_init(container: &str, payload: &str) -> Response {
     let manager = get_container_manager();
-    let _lock = manager.get_container_lock(container);
+    let lock = manager.get_container_lock(container);
+    let _guard = lock.lock().unwrap();
     
     let container_name = container.to_string();
+    let config = configuration::get_config();
+    let id_field = &config.id_field;
     
-    thread::scope(|s| {
-        s.spawn(|| {
-            let container_file = format!("tree/{}.json", container_name);
-            
-            if !Path::new(&container_file).exists() {
-                return "ERROR: Container does not exist".to_string();
+    run_handler(|| {
+        let Some(template) = manager.get_template(&container_name) else {
+            return Response::Error(ErrorCode::ContainerNotFound, "Container not found in tree.json".to_string());
+        };
+        
+        let modules = match serde_json::from_str::<Vec<serde_json::Value>>(payload) {
+            Ok(modules) => modules,
+            Err(_) => return Response::Error(ErrorCode::InvalidData, "Modules must be a JSON array of objects".to_string()),
+        };
+        
+        let mut current_data = if container_exists(&container_name) {
+            match read_container(&container_name) {
+                Ok(data) => data,
+                Err(e) => return e,
             }
-            
-            let content = match fs::read_to_string(&container_file) {
-                Ok(content) => content,
-                Err(_) => return "ERROR: Failed to read container file".to_string(),
+        } else {
+            Vec::new()
+        };
+        
+        let purged = purge_expired(&mut current_data) > 0;
+        if purged {
+            manager.invalidate_module_index(&container_name);
+        }
+        
+        // Ids already in the container plus those accepted from this payload
+        let mut existing_ids: HashSet<String> = current_data
+            .iter()
+            .filter_map(|item| module_id(item, id_field))
+            .map(|id| normalize_id(id).into_owned())
+            .collect();
+        let mut new_modules = Vec::with_capacity(modules.len());
+        let mut rejected = Vec::new();
+        
+        for (position, module) in modules.into_iter().enumerate() {
+            let serde_json::Value::Object(mut module) = module else {
+                return Response::Error(ErrorCode::InvalidData, format!("Module {} is not a JSON object", position));
             };
             
-            let data: serde_json::Value = match serde_json::from_str(&content) {
-                Ok(data) => data,
-                Err(_) => return "ERROR: Failed to parse container file".to_string(),
+            let module_name = match module.get(id_field).and_then(|id| id.as_str()) {
+                Some(id) if !id.is_empty() => normalize_id(id).into_owned(),
+                _ => return Response::Error(ErrorCode::InvalidData, format!("Module {} needs a string '{}' field", position, id_field)),
             };
             
-            if let Some(array) = data.as_array() {
-                let modules: Vec<String> = array
-                    .iter()
-                    .filter_map(|item| item.as_object())
-                    .filter_map(|obj| obj.get("id"))
-                    .filter_map(|id| id.as_str())
-                    .map(|s| s.to_string())
-                    .collect();
-                
-                modules.join(", ")
-            } else {
-                "ERROR: Invalid container format".to_string()
+            if existing_ids.contains(&module_name) {
+                rejected.push(module_name);
+                continue;
+            }
+            
+            module.insert(id_field.clone(), serde_json::Value::String(module_name.clone()));
+            if config.track_versions {
+                module.remove(VERSION_FIELD);
+            }
+            
+            if config.strict_mode
+                && let Some(key) = module.keys().find(|key| template.get(key.as_str()).is_none())
+            {
+                return Response::Error(ErrorCode::FieldNotAllowed, format!("Field '{}' not in template", key));
+            }
+            
+            touch_module(&mut module);
+            
+            if let Err(e) = manager.validate_module(&container_name, &module) {
+                return e;
+            }
+            
+            existing_ids.insert(module_name);
+            new_modules.push(serde_json::Value::Object(module));
+        }
+        
+        if exceeds_module_limit(current_data.len() + new_modules.len()) {
+            return Response::Error(ErrorCode::ContainerFull, "Container full".to_string());
+        }
+        
+        let inserted = new_modules.len();
+        if inserted > 0 || purged {
+            current_data.extend(new_modules);
+            
+            if let Err(e) = write_container(&container_name, &current_data) {
+                return e;
             }
-        }).join().unwrap_or_else(|_| "ERROR: Thread panic".to_string())
+        }
+        
+        if rejected.is_empty() {
+            Response::Ok(format!("BULKINIT {} in Container '{}'", inserted, container_name))
+        } else {
+            Response::Ok(format!("BULKINIT {} in Container '{}', rejected duplicates: {}", inserted, container_name, rejected.join(", ")))
+        }
     })
 }
 
-pub fn handle_list_keys(container: &str, module: &str) -> String {
+pub fn handle_set(container: &str, module: &str, key: &str, value: &str) -> Response {
+    set_field(container, module, key, serde_json::Value::String(value.to_string()))
+}
+
+/// Stores a value parsed as JSON, keeping its type instead of the text SET would store.
+pub fn handle_set_json(container: &str, module: &str, key: &str, json: &str) -> Response {
+    match serde_json::from_str(json) {
+        Ok(value) => set_field(container, module, key, value),
+        Err(e) => Response::Error(ErrorCode::InvalidData, format!("Invalid JSON value: {}", e)),
+    }
+}
+
+/// Stores a binary value sent as standard base64. The value is kept tagged as
+/// `{"$binary": "<base64>"}` and GET returns the base64 text again.
+pub fn handle_set_binary(container: &str, module: &str, key: &str, encoded: &str) -> Response {
+    let bytes = match BASE64.decode(encoded) {
+        Ok(bytes) => bytes,
+        Err(_) => return Response::Error(ErrorCode::InvalidData, "Value is not valid base64".to_string()),
+    };
+    
+    set_field(container, module, key, serde_json::json!({ BINARY_TAG: BASE64.encode(bytes) }))
+}
+
+fn set_field(container: &str, module: &str, key: &str, value: serde_json::Value) -> Response {
     let manager = get_container_manager();
-    let _lock = manager.get_container_lock(container);
+    let lock = manager.get_container_lock(container);
+    let _guard = lock.lock().unwrap();
     
     let container_name = container.to_string();
     let module_name = module.to_string();
+    let key_name = key.to_string();
+    
+    let id_field = &configuration::get_config().id_field;
     
-    thread::scope(|s| {
-        s.spawn(|| {
-            let container_file = format!("tree/{}.json", container_name);
+    if let Err(e) = check_writable_key(key) {
+        return e;
+    }
+    
+    run_handler(|| {
+        let mut current_data = match read_container(&container_name) {
+            Ok(data) => data,
+            Err(e) => return e,
+        };
+        
+        let module_obj = find_module_mut(&container_name, &mut current_data, id_field, &module_name);
+        
+        if let Some(obj) = module_obj {
+            let reply = Response::Ok(format!("SET {} {}", key_name, value_to_string(&value)));
+            obj.insert(key_name.clone(), value.clone());
+            forget_renamed_module(&container_name, &key_name);
+            touch_module(obj);
             
-            if !Path::new(&container_file).exists() {
-                return "ERROR: Container does not exist".to_string();
+            if let Err(e) = manager.validate_module(&container_name, obj) {
+                return e;
             }
             
-            let content = match fs::read_to_string(&container_file) {
-                Ok(content) => content,
-                Err(_) => return "ERROR: Failed to read container file".to_string(),
-            };
+            if let Err(e) = write_container(&container_name, &current_data) {
+                return e;
+            }
             
-            let data: serde_json::Value = match serde_json::from_str(&content) {
-                Ok(data) => data,
-                Err(_) => return "ERROR: Failed to parse container file".to_string(),
-            };
+            return reply;
+        }
+        
+        Response::Error(ErrorCode::ModuleNotFound, "Module not found".to_string())
+    })
+}
+
+/// Sets several keys of one module in a single read-modify-write. The write is
+/// all or nothing unless `partial` is set, in which case every key that passes
+/// validation is applied and the reply lists the outcome per key.
+pub fn handle_set_many(container: &str, module: &str, pairs: &[(&str, &str)], partial: bool) -> Response {
+    let manager = get_container_manager();
+    let lock = manager.get_container_lock(container);
+    let _guard = lock.lock().unwrap();
+    
+    let container_name = container.to_string();
+    let module_name = module.to_string();
+    
+    let id_field = &configuration::get_config().id_field;
+    
+    run_handler(|| {
+        let mut current_data = match read_container(&container_name) {
+            Ok(data) => data,
+            Err(e) => return e,
+        };
+        
+        let Some(obj) = find_module_mut(&container_name, &mut current_data, id_field, &module_name) else {
+            return Response::Error(ErrorCode::ModuleNotFound, "Module not found".to_string());
+        };
+        
+        if partial {
+            let mut statuses = Vec::with_capacity(pairs.len());
+            let mut applied = 0;
             
-            if let Some(array) = data.as_array() {
-                for item in array {
-                    if let Some(obj) = item.as_object() {
-                        if obj.get("id").and_then(|v| v.as_str()) == Some(&module_name) {
-                            let keys: Vec<String> = obj
-                                .keys()
-                                .filter(|&k| k != "id")
-                                .map(|k| k.to_string())
-                                .collect();
-                            
-                            return keys.join(", ");
-                        }
+            for (key, value) in pairs {
+                if let Err(e) = check_writable_key(key) {
+                    statuses.push(format!("{} {}", key, e.to_wire()));
+                    continue;
+                }
+                
+                let previous = obj.insert(key.to_string(), serde_json::Value::String(value.to_string()));
+                
+                match manager.validate_module(&container_name, obj) {
+                    Ok(()) => {
+                        forget_renamed_module(&container_name, key);
+                        applied += 1;
+                        statuses.push(format!("{} OK", key));
+                    }
+                    Err(e) => {
+                        match previous {
+                            Some(previous) => obj.insert(key.to_string(), previous),
+                            None => obj.remove(*key),
+                        };
+                        statuses.push(format!("{} {}", key, e.to_wire()));
                     }
                 }
             }
             
-            "ERROR: Module not found".to_string()
-        }).join().unwrap_or_else(|_| "ERROR: Thread panic".to_string())
+            if applied > 0 {
+                touch_module(obj);
+                
+                if let Err(e) = write_container(&container_name, &current_data) {
+                    return e;
+                }
+            }
+            
+            return Response::Ok(format!("SETM {}/{}\n{}", applied, pairs.len(), statuses.join("\n")));
+        }
+        
+        for (key, value) in pairs {
+            if let Err(e) = check_writable_key(key) {
+                return e;
+            }
+            obj.insert(key.to_string(), serde_json::Value::String(value.to_string()));
+            forget_renamed_module(&container_name, key);
+        }
+        touch_module(obj);
+        
+        if let Err(e) = manager.validate_module(&container_name, obj) {
+            return e;
+        }
+        
+        if let Err(e) = write_container(&container_name, &current_data) {
+            return e;
+        }
+        
+        Response::Ok(format!("SETM {}", pairs.len()))
     })
 }
 
-fn replace_placeholder(container: &mut serde_json::Value, replacement_value: &str) {
-    match container {
-        serde_json::Value::Object(obj) => {
-            let mut new_obj = serde_json::Map::new();
-            for (key, val) in obj {
-                if key == "id" {
-                    new_obj.insert(key.clone(), serde_json::Value::String(replacement_value.to_string()));
-                } else {
-                    let mut new_val = val.clone();
-                    replace_placeholder(&mut new_val, replacement_value);
-                    new_obj.insert(key.clone(), new_val);
+/// Sets a key only when the module does not have it yet. Replies `EXISTS`
+/// and leaves the module untouched otherwise.
+pub fn handle_setnx(container: &str, module: &str, key: &str, value: &str) -> Response {
+    let manager = get_container_manager();
+    let lock = manager.get_container_lock(container);
+    let _guard = lock.lock().unwrap();
+    
+    let container_name = container.to_string();
+    let module_name = module.to_string();
+    let key_name = key.to_string();
+    
+    let id_field = &configuration::get_config().id_field;
+    
+    if let Err(e) = check_writable_key(key) {
+        return e;
+    }
+    
+    run_handler(|| {
+        let mut current_data = match read_container(&container_name) {
+            Ok(data) => data,
+            Err(e) => return e,
+        };
+        
+        let Some(obj) = find_module_mut(&container_name, &mut current_data, id_field, &module_name) else {
+            return Response::Error(ErrorCode::ModuleNotFound, "Module not found".to_string());
+        };
+        
+        if obj.contains_key(&key_name) {
+            return Response::Ok("EXISTS".to_string());
+        }
+        
+        obj.insert(key_name.clone(), serde_json::Value::String(value.to_string()));
+        forget_renamed_module(&container_name, &key_name);
+        touch_module(obj);
+        
+        if let Err(e) = manager.validate_module(&container_name, obj) {
+            return e;
+        }
+        
+        if let Err(e) = write_container(&container_name, &current_data) {
+            return e;
+        }
+        
+        Response::Ok(format!("SET {} {}", key_name, value))
+    })
+}
+
+/// Replaces a value only if it currently equals `expected`, compared in its GET
+/// form. On a mismatch the error carries the actual value, or `(nil)` if unset.
+pub fn handle_cas(container: &str, module: &str, key: &str, expected: &str, new_value: &str) -> Response {
+    let manager = get_container_manager();
+    let lock = manager.get_container_lock(container);
+    let _guard = lock.lock().unwrap();
+    
+    let container_name = container.to_string();
+    let module_name = module.to_string();
+    let key_name = key.to_string();
+    
+    let id_field = &configuration::get_config().id_field;
+    
+    if let Err(e) = check_writable_key(key) {
+        return e;
+    }
+    
+    run_handler(|| {
+        let mut current_data = match read_container(&container_name) {
+            Ok(data) => data,
+            Err(e) => return e,
+        };
+        
+        let Some(obj) = find_module_mut(&container_name, &mut current_data, id_field, &module_name) else {
+            return Response::Error(ErrorCode::ModuleNotFound, "Module not found".to_string());
+        };
+        
+        let actual = obj.get(&key_name).map(value_to_string);
+        if actual.as_deref() != Some(expected) {
+            return Response::Error(ErrorCode::CasMismatch, format!("CAS mismatch: {}", actual.as_deref().unwrap_or(MISSING_VALUE)));
+        }
+        
+        obj.insert(key_name.clone(), serde_json::Value::String(new_value.to_string()));
+        forget_renamed_module(&container_name, &key_name);
+        touch_module(obj);
+        
+        if let Err(e) = manager.validate_module(&container_name, obj) {
+            return e;
+        }
+        
+        if let Err(e) = write_container(&container_name, &current_data) {
+            return e;
+        }
+        
+        Response::Ok("OK".to_string())
+    })
+}
+
+/// Subtracts `amount` from a numeric field and returns the new value. A missing
+/// key counts as 0; strings holding integers are accepted and kept as strings.
+pub fn handle_decr(container: &str, module: &str, key: &str, amount: i64) -> Response {
+    let manager = get_container_manager();
+    let lock = manager.get_container_lock(container);
+    let _guard = lock.lock().unwrap();
+    
+    let container_name = container.to_string();
+    let module_name = module.to_string();
+    let key_name = key.to_string();
+    
+    let id_field = &configuration::get_config().id_field;
+    
+    if let Err(e) = check_writable_key(key) {
+        return e;
+    }
+    
+    run_handler(|| {
+        let mut current_data = match read_container(&container_name) {
+            Ok(data) => data,
+            Err(e) => return e,
+        };
+        
+        let module_obj = find_module_mut(&container_name, &mut current_data, id_field, &module_name);
+        
+        let Some(obj) = module_obj else {
+            return Response::Error(ErrorCode::ModuleNotFound, "Module not found".to_string());
+        };
+        
+        let (current, stored_as_string) = match obj.get(&key_name) {
+            None => (0, false),
+            Some(serde_json::Value::Number(n)) => match n.as_i64() {
+                Some(n) => (n, false),
+                None => return Response::Error(ErrorCode::NotANumber, "Not a number".to_string()),
+            },
+            Some(serde_json::Value::String(s)) => match s.trim().parse::<i64>() {
+                Ok(n) => (n, true),
+                Err(_) => return Response::Error(ErrorCode::NotANumber, "Not a number".to_string()),
+            },
+            Some(_) => return Response::Error(ErrorCode::NotANumber, "Not a number".to_string()),
+        };
+        
+        let Some(updated) = current.checked_sub(amount) else {
+            return Response::Error(ErrorCode::OutOfRange, "Number out of range".to_string());
+        };
+        
+        let new_value = if stored_as_string {
+            serde_json::Value::String(updated.to_string())
+        } else {
+            serde_json::Value::from(updated)
+        };
+        
+        obj.insert(key_name.clone(), new_value);
+        forget_renamed_module(&container_name, &key_name);
+        touch_module(obj);
+        
+        if let Err(e) = manager.validate_module(&container_name, obj) {
+            return e;
+        }
+        
+        if let Err(e) = write_container(&container_name, &current_data) {
+            return e;
+        }
+        
+        Response::Ok(updated.to_string())
+    })
+}
+
+/// Sets a module's `expires_at` to `ttl_seconds` from now, or removes it when
+/// `ttl_seconds` is `None` so the module no longer expires.
+pub fn handle_expire(container: &str, module: &str, ttl_seconds: Option<u64>) -> Response {
+    let manager = get_container_manager();
+    let lock = manager.get_container_lock(container);
+    let _guard = lock.lock().unwrap();
+    
+    let container_name = container.to_string();
+    let module_name = module.to_string();
+    
+    let id_field = &configuration::get_config().id_field;
+    
+    run_handler(|| {
+        let mut current_data = match read_container(&container_name) {
+            Ok(data) => data,
+            Err(e) => return e,
+        };
+        
+        let Some(obj) = find_module_mut(&container_name, &mut current_data, id_field, &module_name) else {
+            return Response::Error(ErrorCode::ModuleNotFound, "Module not found".to_string());
+        };
+        
+        let reply = match ttl_seconds {
+            Some(ttl_seconds) => {
+                let expires_at = now_millis().saturating_add(ttl_seconds.saturating_mul(1000));
+                obj.insert(EXPIRES_AT_FIELD.to_string(), serde_json::Value::from(expires_at));
+                Response::Ok(format!("EXPIRE {} {}", module_name, ttl_seconds))
+            }
+            None => {
+                let reply = Response::Ok(format!("PERSIST {}", module_name));
+                
+                // Nothing to write for a module that never expired
+                if obj.remove(EXPIRES_AT_FIELD).is_none() {
+                    return reply;
                 }
+                reply
             }
-            *container = serde_json::Value::Object(new_obj);
+        };
+        touch_module(obj);
+        
+        if let Err(e) = write_container(&container_name, &current_data) {
+            return e;
         }
-        serde_json::Value::Array(arr) => {
-            let new_arr: Vec<serde_json::Value> = arr.iter()
-                .map(|v| {
-                    let mut new_v = v.clone();
-                    replace_placeholder(&mut new_v, replacement_value);
-                    new_v
-                })
-                .collect();
-            *container = serde_json::Value::Array(new_arr);
+        
+        reply
+    })
+}
+
+pub fn handle_get(container: &str, module: &str, key: &str) -> Response {
+    let manager = get_container_manager();
+    let lock = manager.get_container_lock(container);
+    let _guard = lock.lock().unwrap();
+    
+    let container_name = container.to_string();
+    let module_name = module.to_string();
+    let key_name = key.to_string();
+    
+    let id_field = &configuration::get_config().id_field;
+    
+    run_handler(|| {
+        let data = match read_container(&container_name) {
+            Ok(data) => data,
+            Err(e) => return e,
+        };
+        
+        let Some(obj) = find_module(&container_name, &data, id_field, &module_name) else {
+            return Response::Error(ErrorCode::ModuleNotFound, "Module not found".to_string());
+        };
+        
+        match obj.get(&key_name) {
+            // A nested object or array comes back whole, as its JSON text
+            Some(value) if (value.is_object() || value.is_array()) && binary_value(value).is_none() => Response::Json(value.clone()),
+            Some(value) => Response::Ok(value_to_string(value)),
+            None => Response::Error(ErrorCode::KeyNotFound, "Key not found".to_string()),
         }
-        _ => {}
+    })
+}
+
+/// Reads one key of a container's metadata, kept in `tree/<container>.meta.json`.
+pub fn handle_meta_get(container: &str, key: &str) -> Response {
+    let manager = get_container_manager();
+    let lock = manager.get_meta_lock(container);
+    let _guard = lock.lock().unwrap();
+    
+    let container_name = container.to_string();
+    
+    run_handler(|| {
+        if manager.get_template(&container_name).is_none() {
+            return Response::Error(ErrorCode::ContainerNotFound, "Container not found in tree.json".to_string());
+        }
+        
+        let meta = match read_meta(&container_name) {
+            Ok(meta) => meta,
+            Err(e) => return e,
+        };
+        
+        match meta.get(key) {
+            Some(value) => Response::Ok(value_to_string(value)),
+            None => Response::Error(ErrorCode::KeyNotFound, "Key not found".to_string()),
+        }
+    })
+}
+
+pub fn handle_meta_set(container: &str, key: &str, value: &str) -> Response {
+    let manager = get_container_manager();
+    let lock = manager.get_meta_lock(container);
+    let _guard = lock.lock().unwrap();
+    
+    let container_name = container.to_string();
+    
+    run_handler(|| {
+        if manager.get_template(&container_name).is_none() {
+            return Response::Error(ErrorCode::ContainerNotFound, "Container not found in tree.json".to_string());
+        }
+        
+        let mut meta = match read_meta(&container_name) {
+            Ok(meta) => meta,
+            Err(e) => return e,
+        };
+        
+        meta.insert(key.to_string(), serde_json::Value::String(value.to_string()));
+        
+        if let Err(e) = write_meta(&container_name, &meta) {
+            return e;
+        }
+        
+        Response::Ok(format!("SET {} {}", key, value))
+    })
+}
+
+/// Returns the requested keys of one module as a list of values, with
+/// `MISSING_VALUE` standing in for keys the module does not have.
+pub fn handle_mget(container: &str, module: &str, keys: &[&str]) -> Response {
+    let manager = get_container_manager();
+    let lock = manager.get_container_lock(container);
+    let _guard = lock.lock().unwrap();
+    
+    let container_name = container.to_string();
+    let module_name = module.to_string();
+    
+    let id_field = &configuration::get_config().id_field;
+    
+    run_handler(|| {
+        let data = match read_container(&container_name) {
+            Ok(data) => data,
+            Err(e) => return e,
+        };
+        
+        let Some(obj) = find_module(&container_name, &data, id_field, &module_name) else {
+            return Response::Error(ErrorCode::ModuleNotFound, "Module not found".to_string());
+        };
+        
+        let values: Vec<String> = keys
+            .iter()
+            .map(|key| obj.get(*key).map_or_else(|| MISSING_VALUE.to_string(), value_to_string))
+            .collect();
+        
+        Response::List(values)
+    })
+}
+
+pub fn handle_get_all(container: &str, module: &str) -> Response {
+    let manager = get_container_manager();
+    let lock = manager.get_container_lock(container);
+    let _guard = lock.lock().unwrap();
+    
+    let container_name = container.to_string();
+    let module_name = module.to_string();
+    
+    let id_field = &configuration::get_config().id_field;
+    
+    run_handler(|| {
+        let data = match read_container(&container_name) {
+            Ok(data) => data,
+            Err(e) => return e,
+        };
+        
+        if let Some(obj) = find_module(&container_name, &data, id_field, &module_name) {
+            return match serde_json::to_string(obj) {
+                Ok(json) => Response::Ok(json),
+                Err(_) => Response::Error(ErrorCode::StorageError, "Failed to format data".to_string()),
+            };
+        }
+        
+        Response::Error(ErrorCode::ModuleNotFound, "Module not found".to_string())
+    })
+}
+
+/// Returns several whole modules as JSON, one per line in the order asked for,
+/// with `MISSING_VALUE` for ids the container does not have.
+pub fn handle_mget_all(container: &str, modules: &[&str]) -> Response {
+    let manager = get_container_manager();
+    let lock = manager.get_container_lock(container);
+    let _guard = lock.lock().unwrap();
+    
+    let container_name = container.to_string();
+    
+    let id_field = &configuration::get_config().id_field;
+    
+    run_handler(|| {
+        let data = match read_container(&container_name) {
+            Ok(data) => data,
+            Err(e) => return e,
+        };
+        
+        let mut lines = Vec::with_capacity(modules.len());
+        for module_name in modules {
+            match find_module(&container_name, &data, id_field, module_name) {
+                Some(obj) => match serde_json::to_string(obj) {
+                    Ok(json) => lines.push(json),
+                    Err(_) => return Response::Error(ErrorCode::StorageError, "Failed to format data".to_string()),
+                },
+                None => lines.push(MISSING_VALUE.to_string()),
+            }
+        }
+        
+        // Always one module per line, since the JSON of a module contains commas
+        Response::Lines(lines)
+    })
+}
+
+/// Returns the `_version` of a module, which every write to it increases.
+pub fn handle_module_version(container: &str, module: &str) -> Response {
+    let manager = get_container_manager();
+    let lock = manager.get_container_lock(container);
+    let _guard = lock.lock().unwrap();
+    
+    let container_name = container.to_string();
+    let module_name = module.to_string();
+    
+    let id_field = &configuration::get_config().id_field;
+    
+    run_handler(|| {
+        let data = match read_container(&container_name) {
+            Ok(data) => data,
+            Err(e) => return e,
+        };
+        
+        match find_module(&container_name, &data, id_field, &module_name) {
+            Some(obj) => Response::Ok(module_version_of(obj).to_string()),
+            None => Response::Error(ErrorCode::ModuleNotFound, "Module not found".to_string()),
+        }
+    })
+}
+
+/// Optional filters applied by `LIST <container> ...`.
+#[derive(Default)]
+pub struct ListOptions<'a> {
+    pub prefix: Option<&'a str>,
+    pub limit: Option<usize>,
+    pub offset: usize,
+    /// Field to order the modules by instead of insertion order.
+    pub sort_key: Option<&'a str>,
+    pub descending: bool,
+}
+
+/// Sort key of a field value: numbers order numerically and before any text.
+#[derive(PartialEq, PartialOrd)]
+enum SortValue {
+    Number(f64),
+    Text(String),
+}
+
+impl SortValue {
+    fn of(value: &serde_json::Value) -> Self {
+        let text = value_to_string(value);
+        match text.trim().parse::<f64>() {
+            Ok(number) if number.is_finite() => SortValue::Number(number),
+            _ => SortValue::Text(text),
+        }
+    }
+}
+
+pub fn handle_list_containers() -> Response {
+    Response::List(get_container_manager().container_names())
+}
+
+/// Bytes a container takes on disk, or with no container the bytes of every
+/// container plus tree.json.
+pub fn handle_size(container: Option<&str>) -> Response {
+    let size = match container {
+        Some(container_name) => {
+            if let Err(e) = check_container_name(container_name) {
+                return e;
+            }
+            container_size(container_name)
+        }
+        None => {
+            let containers: u64 = get_container_manager().container_names().iter().map(|name| container_size(name)).sum();
+            containers + fs::metadata(data_path("tree.json")).map_or(0, |metadata| metadata.len())
+        }
+    };
+    
+    Response::Ok(size.to_string())
+}
+
+pub fn handle_list_modules(container: &str, options: &ListOptions) -> Response {
+    let manager = get_container_manager();
+    let lock = manager.get_container_lock(container);
+    let _guard = lock.lock().unwrap();
+    
+    let container_name = container.to_string();
+    
+    let id_field = &configuration::get_config().id_field;
+    
+    run_handler(|| {
+        let data = match read_container(&container_name) {
+            Ok(data) => data,
+            Err(e) => return e,
+        };
+        
+        let now = now_millis();
+        let mut items: Vec<&serde_json::Value> = data.iter().filter(|item| !is_expired(item, now)).collect();
+        
+        if let Some(sort_key) = options.sort_key {
+            // Stable, so ties keep insertion order; modules without the key go last either way
+            items.sort_by(|a, b| {
+                match (a.get(sort_key).map(SortValue::of), b.get(sort_key).map(SortValue::of)) {
+                    (Some(a), Some(b)) => {
+                        let ordering = a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal);
+                        if options.descending { ordering.reverse() } else { ordering }
+                    }
+                    (Some(_), None) => std::cmp::Ordering::Less,
+                    (None, Some(_)) => std::cmp::Ordering::Greater,
+                    (None, None) => std::cmp::Ordering::Equal,
+                }
+            });
+        }
+        
+        let modules: Vec<String> = items
+            .into_iter()
+            .filter_map(|item| module_id(item, id_field))
+            .filter(|id| options.prefix.is_none_or(|prefix| normalize_id(id).starts_with(normalize_id(prefix).as_ref())))
+            .skip(options.offset)
+            .take(options.limit.unwrap_or(usize::MAX))
+            .map(|s| s.to_string())
+            .collect();
+        
+        Response::List(modules)
+    })
+}
+
+/// Comparison operators accepted by FIND.
+#[derive(Clone, Copy)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Gt,
+    Lt,
+    Gte,
+    Lte,
+}
+
+impl Comparison {
+    pub fn parse(operator: &str) -> Option<Self> {
+        match operator.to_lowercase().as_str() {
+            "eq" => Some(Comparison::Eq),
+            "ne" => Some(Comparison::Ne),
+            "gt" => Some(Comparison::Gt),
+            "lt" => Some(Comparison::Lt),
+            "gte" => Some(Comparison::Gte),
+            "lte" => Some(Comparison::Lte),
+            _ => None,
+        }
+    }
+    
+    /// Compares numerically when both sides parse as numbers and lexically otherwise.
+    fn matches(self, actual: &str, expected: &str) -> bool {
+        let ordering = match (actual.trim().parse::<f64>(), expected.trim().parse::<f64>()) {
+            (Ok(actual), Ok(expected)) => match actual.partial_cmp(&expected) {
+                Some(ordering) => ordering,
+                None => return false,
+            },
+            _ => actual.cmp(expected),
+        };
+        
+        match self {
+            Comparison::Eq => ordering.is_eq(),
+            Comparison::Ne => ordering.is_ne(),
+            Comparison::Gt => ordering.is_gt(),
+            Comparison::Lt => ordering.is_lt(),
+            Comparison::Gte => ordering.is_ge(),
+            Comparison::Lte => ordering.is_le(),
+        }
+    }
+}
+
+/// Lists the ids of modules whose `key` satisfies the comparison. Modules
+/// without the key never match.
+pub fn handle_find(container: &str, key: &str, comparison: Comparison, value: &str) -> Response {
+    let manager = get_container_manager();
+    let lock = manager.get_container_lock(container);
+    let _guard = lock.lock().unwrap();
+    
+    let container_name = container.to_string();
+    
+    let id_field = &configuration::get_config().id_field;
+    
+    run_handler(|| {
+        let data = match read_container(&container_name) {
+            Ok(data) => data,
+            Err(e) => return e,
+        };
+        
+        let now = now_millis();
+        let modules: Vec<String> = data
+            .iter()
+            .filter(|item| !is_expired(item, now))
+            .filter(|item| {
+                item.get(key)
+                    .is_some_and(|actual| comparison.matches(&value_to_string(actual), value))
+            })
+            .filter_map(|item| module_id(item, id_field))
+            .map(|s| s.to_string())
+            .collect();
+        
+        Response::List(modules)
+    })
+}
+
+/// Returns `key` of every module that has it, as a list of `id=value` items.
+pub fn handle_pluck(container: &str, key: &str) -> Response {
+    let manager = get_container_manager();
+    let lock = manager.get_container_lock(container);
+    let _guard = lock.lock().unwrap();
+    
+    let container_name = container.to_string();
+    
+    let id_field = &configuration::get_config().id_field;
+    
+    run_handler(|| {
+        let data = match read_container(&container_name) {
+            Ok(data) => data,
+            Err(e) => return e,
+        };
+        
+        let now = now_millis();
+        let lines: Vec<String> = data
+            .iter()
+            .filter(|item| !is_expired(item, now))
+            .filter_map(|item| Some(format!("{}={}", module_id(item, id_field)?, value_to_string(item.get(key)?))))
+            .collect();
+        
+        Response::List(lines)
+    })
+}
+
+/// Lists the keys of a module without the fields the server maintains; `_version`
+/// is included when `include_version` is set.
+pub fn handle_list_keys(container: &str, module: &str, include_version: bool) -> Response {
+    let manager = get_container_manager();
+    let lock = manager.get_container_lock(container);
+    let _guard = lock.lock().unwrap();
+    
+    let container_name = container.to_string();
+    let module_name = module.to_string();
+    
+    let id_field = &configuration::get_config().id_field;
+    
+    run_handler(|| {
+        let data = match read_container(&container_name) {
+            Ok(data) => data,
+            Err(e) => return e,
+        };
+        
+        if let Some(obj) = find_module(&container_name, &data, id_field, &module_name) {
+            let keys: Vec<String> = obj
+                .keys()
+                .filter(|&k| k != id_field && k != UPDATED_AT_FIELD && k != EXPIRES_AT_FIELD)
+                .filter(|&k| include_version || k != VERSION_FIELD)
+                .map(|k| k.to_string())
+                .collect();
+            
+            return Response::List(keys);
+        }
+        
+        Response::Error(ErrorCode::ModuleNotFound, "Module not found".to_string())
+    })
+}
+
+pub fn handle_dump(container: &str) -> Response {
+    let manager = get_container_manager();
+    let lock = manager.get_container_lock(container);
+    let _guard = lock.lock().unwrap();
+    
+    let container_name = container.to_string();
+    
+    run_handler(|| {
+        let data = match read_container(&container_name) {
+            Ok(data) => data,
+            Err(e) => return e,
+        };
+        
+        Response::Json(serde_json::Value::Array(data))
+    })
+}
+
+/// Dumps every registered container as one JSON object keyed by container name.
+/// Containers are read one at a time as the reply is written, each locked only
+/// while it is being read, so only one of them is held in memory.
+pub fn handle_dump_all() -> Response {
+    Response::JsonStream(JsonStream::new(|writer| {
+        let manager = get_container_manager();
+        
+        writer.write_all(b"{")?;
+        for (index, container_name) in manager.container_names().into_iter().enumerate() {
+            let data = {
+                let lock = manager.get_container_lock(&container_name);
+                let _guard = lock.lock().unwrap();
+                read_container(&container_name).map_err(|e| io::Error::other(e.to_wire()))?
+            };
+            
+            if index > 0 {
+                writer.write_all(b",")?;
+            }
+            serde_json::to_writer(&mut *writer, &container_name)?;
+            writer.write_all(b":[")?;
+            for (position, module) in data.iter().enumerate() {
+                if position > 0 {
+                    writer.write_all(b",")?;
+                }
+                serde_json::to_writer(&mut *writer, module)?;
+            }
+            writer.write_all(b"]")?;
+        }
+        writer.write_all(b"}")
+    }))
+}
+
+/// Replaces a container's contents with a JSON array of modules. Containers
+/// that are not yet in tree.json are registered with a minimal template.
+pub fn handle_import(container: &str, payload: &str) -> Response {
+    let manager = get_container_manager();
+    let lock = manager.get_container_lock(container);
+    let _guard = lock.lock().unwrap();
+    
+    let container_name = container.to_string();
+    let id_field = &configuration::get_config().id_field;
+    
+    run_handler(|| {
+        let data: Vec<serde_json::Value> = match serde_json::from_str(payload) {
+            Ok(data) => data,
+            Err(_) => return Response::Error(ErrorCode::InvalidData, "Invalid import data".to_string()),
+        };
+        
+        if !data.iter().all(|item| module_id(item, id_field).is_some()) {
+            return Response::Error(ErrorCode::InvalidData, "Invalid import data".to_string());
+        }
+        
+        if exceeds_module_limit(data.len()) {
+            return Response::Error(ErrorCode::ContainerFull, "Container full".to_string());
+        }
+        
+        for obj in data.iter().filter_map(|item| item.as_object()) {
+            if let Err(e) = manager.validate_module(&container_name, obj) {
+                return e;
+            }
+        }
+        
+        if manager.get_template(&container_name).is_none() {
+            let template = serde_json::json!({ id_field.as_str(): "" });
+            if let Err(e) = manager.register_container(&container_name, template) {
+                return e;
+            }
+        }
+        
+        if let Err(e) = write_container(&container_name, &data) {
+            return e;
+        }
+        
+        manager.invalidate_module_index(&container_name);
+        
+        Response::Ok(format!("IMPORT {} modules into Container '{}'", data.len(), container_name))
+    })
+}
+
+/// Removes a module from `source` and appends it to `target` as one operation.
+/// Both container locks are taken in name order so concurrent moves cannot deadlock.
+pub fn handle_move(source: &str, module: &str, target: &str) -> Response {
+    let manager = get_container_manager();
+    
+    let locks = manager.get_container_locks(&[source, target]);
+    let _guards = lock_containers(&locks);
+    
+    let source_name = source.to_string();
+    let target_name = target.to_string();
+    let module_name = module.to_string();
+    
+    let id_field = &configuration::get_config().id_field;
+    
+    run_handler(|| {
+        if manager.get_template(&target_name).is_none() {
+            return Response::Error(ErrorCode::ContainerNotFound, "Container not found in tree.json".to_string());
+        }
+        
+        let mut source_data = match read_container(&source_name) {
+            Ok(data) => data,
+            Err(e) => return e,
+        };
+        
+        let position = manager.module_position(&source_name, &source_data, id_field, &module_name)
+            .filter(|&position| !is_expired(&source_data[position], now_millis()));
+        let Some(position) = position else {
+            return Response::Error(ErrorCode::ModuleNotFound, "Module not found".to_string());
+        };
+        
+        let mut target_data = if container_exists(&target_name) {
+            match read_container(&target_name) {
+                Ok(data) => data,
+                Err(e) => return e,
+            }
+        } else {
+            Vec::new()
+        };
+        
+        if purge_expired(&mut target_data) > 0 {
+            manager.invalidate_module_index(&target_name);
+        }
+        
+        if find_module(&target_name, &target_data, id_field, &module_name).is_some() {
+            return Response::Error(ErrorCode::TargetExists, "Target exists".to_string());
+        }
+        
+        if exceeds_module_limit(target_data.len() + 1) {
+            return Response::Error(ErrorCode::ContainerFull, "Container full".to_string());
+        }
+        
+        if let Some(obj) = source_data[position].as_object()
+            && let Err(e) = manager.validate_module(&target_name, obj)
+        {
+            return e;
+        }
+        
+        target_data.push(source_data.remove(position));
+        
+        // Write the target first so a failure in between leaves a copy rather than losing the module
+        if let Err(e) = write_container(&target_name, &target_data) {
+            return e;
+        }
+        manager.record_module_position(&target_name, &module_name, target_data.len() - 1);
+        
+        if let Err(e) = write_container(&source_name, &source_data) {
+            return e;
+        }
+        manager.invalidate_module_index(&source_name);
+        
+        Response::Ok(format!("MOVE {} from Container '{}' to Container '{}'", module_name, source_name, target_name))
+    })
+}
+
+/// Checks that every container can be read and holds an array of modules with an
+/// id, reporting the containers that do not.
+pub fn handle_verify() -> Response {
+    let manager = get_container_manager();
+    let id_field = &configuration::get_config().id_field;
+    
+    let mut valid = 0;
+    let mut corrupt = Vec::new();
+    
+    for container_name in manager.container_names() {
+        let lock = manager.get_container_lock(&container_name);
+        let _guard = lock.lock().unwrap();
+        
+        // Containers whose file has not been created yet hold no data to check
+        if !container_exists(&container_name) {
+            valid += 1;
+            continue;
+        }
+        
+        match verify_container(&container_name, id_field) {
+            Ok(()) => valid += 1,
+            Err(reason) => corrupt.push(format!("{}({})", container_name, reason)),
+        }
+    }
+    
+    if corrupt.is_empty() {
+        Response::Ok(format!("OK: {}", valid))
+    } else {
+        Response::Ok(format!("OK: {}, CORRUPT: {}", valid, corrupt.join(", ")))
+    }
+}
+
+fn verify_container(container_name: &str, id_field: &str) -> Result<(), String> {
+    let data = match read_container_value(container_name) {
+        Ok(serde_json::Value::Array(data)) => data,
+        Ok(_) => return Err("not an array".to_string()),
+        Err(Response::Error(_, message)) => return Err(message),
+        Err(_) => return Err("unreadable".to_string()),
+    };
+    
+    for (position, module) in data.iter().enumerate() {
+        if !module.is_object() {
+            return Err(format!("module {} is not an object", position));
+        }
+        if module_id(module, id_field).is_none() {
+            return Err(format!("module {} has no string '{}'", position, id_field));
+        }
+    }
+    
+    Ok(())
+}
+
+/// Wraps a container file that holds a single JSON object into a one-element array.
+pub fn handle_repair(container: &str) -> Response {
+    let manager = get_container_manager();
+    let lock = manager.get_container_lock(container);
+    let _guard = lock.lock().unwrap();
+    
+    let container_name = container.to_string();
+    
+    run_handler(|| {
+        let data = match read_container_value(&container_name) {
+            Ok(data) => data,
+            Err(e) => return e,
+        };
+        
+        match data {
+            serde_json::Value::Array(_) => Response::Ok(format!("REPAIR Container '{}' is already valid", container_name)),
+            serde_json::Value::Object(_) => {
+                if let Err(e) = write_container(&container_name, &[data]) {
+                    return e;
+                }
+                
+                manager.invalidate_module_index(&container_name);
+                Response::Ok(format!("REPAIR Container '{}' wrapped into an array", container_name))
+            }
+            _ => Response::Error(ErrorCode::CorruptData, "Container cannot be repaired".to_string()),
+        }
+    })
+}
+
+/// Rewrites a container in the configured storage format without its expired
+/// modules and reports the size on disk before and after.
+pub fn handle_compact(container: &str) -> Response {
+    let manager = get_container_manager();
+    let lock = manager.get_container_lock(container);
+    let _guard = lock.lock().unwrap();
+    
+    let container_name = container.to_string();
+    
+    run_handler(|| {
+        let before = container_size(&container_name);
+        
+        let mut data = match read_container(&container_name) {
+            Ok(data) => data,
+            Err(e) => return e,
+        };
+        
+        if purge_expired(&mut data) > 0 {
+            manager.invalidate_module_index(&container_name);
+        }
+        
+        if let Err(e) = write_container(&container_name, &data) {
+            return e;
+        }
+        
+        // The coalescer never takes container locks, so waiting here cannot deadlock
+        if let Err(e) = await_staged_writes() {
+            return e;
+        }
+        
+        Response::Ok(format!("COMPACT {} -> {} bytes", before, container_size(&container_name)))
+    })
+}
+
+fn parse_tree(tree_file: &str, tree_content: &str) -> Result<serde_json::Map<String, serde_json::Value>, String> {
+    let tree_data: serde_json::Value = serde_json::from_str(tree_content).map_err(|e| {
+        // serde_json appends its own position, which is reported separately here
+        let message = e.to_string();
+        let reason = message.split(" at line ").next().unwrap_or(&message).to_string();
+        format!("Malformed {} at line {}, column {}: {}", tree_file, e.line(), e.column(), reason)
+    })?;
+    
+    match tree_data {
+        serde_json::Value::Object(root_map) => Ok(root_map),
+        _ => Err(format!("Malformed {}: expected a JSON object of container templates", tree_file)),
+    }
+}
+
+/// Resolves a path relative to the configured `data_dir`.
+fn data_path(relative: &str) -> String {
+    Path::new(&configuration::get_config().data_dir).join(relative).to_string_lossy().into_owned()
+}
+
+/// Name a container is stored under on disk and in tree.json, prefixed with the
+/// configured namespace.
+fn storage_name(container_name: &str) -> Cow<'_, str> {
+    let namespace = &configuration::get_config().namespace;
+    if namespace.is_empty() {
+        Cow::Borrowed(container_name)
+    } else {
+        Cow::Owned(format!("{}_{}", namespace, container_name))
+    }
+}
+
+/// Suffixes of the files stored next to container files. A container whose name
+/// ends in one would share a path with another container's files, such as the
+/// metadata of `x` for a container named `x.meta`, or the shard directory of
+/// `x.json` with the file of `x`.
+const RESERVED_NAME_SUFFIXES: &[&str] = &[".meta", ".json", ".jsonl", ".gz"];
+
+/// Rejects container names that would resolve to a path outside `tree/` or
+/// collide with the files of another container.
+fn check_container_name(container_name: &str) -> Result<(), Response> {
+    let invalid = container_name.is_empty()
+        || container_name.contains(['/', '\\', '\0'])
+        || container_name.contains("..")
+        || container_name == TREE_META_KEY
+        || RESERVED_NAME_SUFFIXES.iter().any(|suffix| container_name.ends_with(suffix));
+    
+    if invalid {
+        return Err(Response::Error(ErrorCode::InvalidName, format!("Invalid container name '{}'", container_name.escape_debug())));
+    }
+    
+    Ok(())
+}
+
+/// Container name behind a name from tree.json or the templates/ directory, or
+/// `None` when it belongs to another namespace.
+fn container_name_of(stored_name: &str) -> Option<&str> {
+    let namespace = &configuration::get_config().namespace;
+    if namespace.is_empty() {
+        return Some(stored_name);
+    }
+    
+    stored_name.strip_prefix(namespace.as_str())?.strip_prefix('_')
+}
+
+/// Keeps the tree.json entries of the configured namespace, keyed by container name.
+fn namespace_entries(root_map: serde_json::Map<String, serde_json::Value>) -> serde_json::Map<String, serde_json::Value> {
+    root_map.into_iter()
+        .filter_map(|(key, template)| Some((container_name_of(&key)?.to_string(), template)))
+        .collect()
+}
+
+/// Containers of the configured namespace that have a file in the templates/ directory.
+fn template_file_containers() -> Vec<String> {
+    let Ok(entries) = fs::read_dir(data_path("templates")) else {
+        return Vec::new();
+    };
+    
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            let stored_name = file_name.strip_suffix(".json")?;
+            container_name_of(stored_name).map(|name| name.to_string())
+        })
+        .collect()
+}
+
+/// Parses a template from the templates/ directory, reporting a malformed file.
+fn read_template_file(container_name: &str) -> Option<serde_json::Value> {
+    let path = template_path(container_name);
+    let content = fs::read_to_string(&path).ok()?;
+    
+    match serde_json::from_str(&content) {
+        Ok(template) => Some(template),
+        Err(e) => {
+            if !configuration::get_config().silent {
+                eprintln!("Malformed {}: {}", path, e);
+            }
+            None
+        }
+    }
+}
+
+fn template_path(container_name: &str) -> String {
+    data_path(&format!("templates/{}.json", storage_name(container_name)))
+}
+
+fn container_path(container_name: &str) -> String {
+    data_path(&format!("tree/{}.json", storage_name(container_name)))
+}
+
+fn compressed_container_path(container_name: &str) -> String {
+    data_path(&format!("tree/{}.json.gz", storage_name(container_name)))
+}
+
+fn jsonl_container_path(container_name: &str) -> String {
+    data_path(&format!("tree/{}.jsonl", storage_name(container_name)))
+}
+
+/// Directory holding the shard files of a container when `shards_per_container` is above 1.
+fn shard_directory(container_name: &str) -> String {
+    data_path(&format!("tree/{}", storage_name(container_name)))
+}
+
+fn shard_path(container_name: &str, shard: usize) -> String {
+    data_path(&format!("tree/{}/shard_{}.json", storage_name(container_name), shard))
+}
+
+fn compressed_shard_path(container_name: &str, shard: usize) -> String {
+    data_path(&format!("tree/{}/shard_{}.json.gz", storage_name(container_name), shard))
+}
+
+fn meta_path(container_name: &str) -> String {
+    data_path(&format!("tree/{}.meta.json", storage_name(container_name)))
+}
+
+fn compressed_meta_path(container_name: &str) -> String {
+    data_path(&format!("tree/{}.meta.json.gz", storage_name(container_name)))
+}
+
+/// Sums the data, shard and metadata files of a container in whatever format they are stored.
+fn container_size(container_name: &str) -> u64 {
+    let files = [
+        container_path(container_name),
+        compressed_container_path(container_name),
+        jsonl_container_path(container_name),
+        meta_path(container_name),
+        compressed_meta_path(container_name),
+    ];
+    let mut size: u64 = files.iter().filter_map(|path| fs::metadata(path).ok()).map(|metadata| metadata.len()).sum();
+    
+    if let Ok(entries) = fs::read_dir(shard_directory(container_name)) {
+        size += entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.metadata().ok())
+            .map(|metadata| metadata.len())
+            .sum::<u64>();
+    }
+    
+    size
+}
+
+fn container_exists(container_name: &str) -> bool {
+    get_container_manager().staged_container(container_name).is_some()
+        || Path::new(&compressed_container_path(container_name)).exists()
+        || Path::new(&container_path(container_name)).exists()
+        || Path::new(&jsonl_container_path(container_name)).exists()
+        || Path::new(&shard_directory(container_name)).is_dir()
+}
+
+/// Reads a container, transparently decompressing it when it is stored as gzip.
+/// Plain files written before `compress_storage` was enabled stay readable, and
+/// a sharded container is read as the concatenation of its shards.
+fn read_container_value(container_name: &str) -> Result<serde_json::Value, Response> {
+    check_container_name(container_name)?;
+    
+    if let Some(staged) = get_container_manager().staged_container(container_name) {
+        return Ok(serde_json::Value::Array(staged.to_vec()));
+    }
+    
+    if Path::new(&shard_directory(container_name)).is_dir() {
+        return read_sharded_container(container_name);
+    }
+    
+    let jsonl_path = jsonl_container_path(container_name);
+    if Path::new(&jsonl_path).exists() {
+        return read_jsonl_container(&jsonl_path);
+    }
+    
+    match read_storage_file(&container_path(container_name), &compressed_container_path(container_name))? {
+        Some(content) => serde_json::from_str(&content).map_err(|e| Response::Error(ErrorCode::CorruptData, format!("Failed to parse container file: {}", e))),
+        None => Err(Response::Error(ErrorCode::ContainerNotFound, "Container does not exist".to_string())),
+    }
+}
+
+fn read_sharded_container(container_name: &str) -> Result<serde_json::Value, Response> {
+    let entries = fs::read_dir(shard_directory(container_name))
+        .map_err(|_| Response::Error(ErrorCode::StorageError, "Failed to read container file".to_string()))?;
+    
+    let mut shards: Vec<usize> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| shard_number(&entry.file_name().to_string_lossy()))
+        .collect();
+    shards.sort_unstable();
+    shards.dedup();
+    
+    let mut modules = Vec::new();
+    
+    for shard in shards {
+        if let Some(content) = read_storage_file(&shard_path(container_name, shard), &compressed_shard_path(container_name, shard))? {
+            let shard_modules: Vec<serde_json::Value> = serde_json::from_str(&content)
+                .map_err(|e| Response::Error(ErrorCode::CorruptData, format!("Failed to parse shard {}: {}", shard, e)))?;
+            modules.extend(shard_modules);
+        }
+    }
+    
+    Ok(serde_json::Value::Array(modules))
+}
+
+fn read_jsonl_container(path: &str) -> Result<serde_json::Value, Response> {
+    let content = fs::read_to_string(path)
+        .map_err(|_| Response::Error(ErrorCode::StorageError, "Failed to read container file".to_string()))?;
+    
+    content.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(number, line)| {
+            serde_json::from_str(line)
+                .map_err(|e| Response::Error(ErrorCode::CorruptData, format!("Failed to parse container file line {}: {}", number + 1, e)))
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map(serde_json::Value::Array)
+}
+
+/// Parses `shard_<k>.json` or `shard_<k>.json.gz` into `k`.
+fn shard_number(file_name: &str) -> Option<usize> {
+    let stem = file_name.strip_suffix(".json.gz").or_else(|| file_name.strip_suffix(".json"))?;
+    stem.strip_prefix("shard_")?.parse().ok()
+}
+
+/// Reads a storage file, preferring its gzip compressed variant. Returns `None`
+/// when neither exists.
+fn read_storage_file(plain_path: &str, compressed_path: &str) -> Result<Option<String>, Response> {
+    if Path::new(compressed_path).exists() {
+        let file = fs::File::open(compressed_path).map_err(|_| Response::Error(ErrorCode::StorageError, "Failed to read container file".to_string()))?;
+        let mut content = String::new();
+        GzDecoder::new(file)
+            .read_to_string(&mut content)
+            .map_err(|_| Response::Error(ErrorCode::CorruptData, "Failed to decompress container file".to_string()))?;
+        Ok(Some(content))
+    } else if Path::new(plain_path).exists() {
+        fs::read_to_string(plain_path)
+            .map(Some)
+            .map_err(|_| Response::Error(ErrorCode::StorageError, "Failed to read container file".to_string()))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Reads a container's module array. Any other JSON shape is reported rather
+/// than treated as empty, so it is never overwritten by accident; REPAIR fixes
+/// the common case of a single stray object.
+fn read_container(container_name: &str) -> Result<Vec<serde_json::Value>, Response> {
+    match read_container_value(container_name)? {
+        serde_json::Value::Array(array) => Ok(array),
+        _ => Err(Response::Error(ErrorCode::CorruptData, "Container is not an array".to_string())),
+    }
+}
+
+/// Reads a container's metadata object; a container without metadata has an empty one.
+fn read_meta(container_name: &str) -> Result<serde_json::Map<String, serde_json::Value>, Response> {
+    check_container_name(container_name)?;
+    
+    match read_storage_file(&meta_path(container_name), &compressed_meta_path(container_name))? {
+        Some(content) => serde_json::from_str(&content).map_err(|_| Response::Error(ErrorCode::CorruptData, "Failed to parse metadata file".to_string())),
+        None => Ok(serde_json::Map::new()),
+    }
+}
+
+fn write_meta(container_name: &str, meta: &serde_json::Map<String, serde_json::Value>) -> Result<(), Response> {
+    check_container_name(container_name)?;
+    
+    if DRY_RUN.get() {
+        return Ok(());
+    }
+    
+    let formatted = if configuration::get_config().pretty_storage {
+        serde_json::to_string_pretty(meta)
+    } else {
+        serde_json::to_string(meta)
+    };
+    let formatted = formatted.map_err(|_| Response::Error(ErrorCode::StorageError, "Failed to format data".to_string()))?;
+    
+    write_storage_file(&meta_path(container_name), &compressed_meta_path(container_name), &formatted)
+}
+
+/// Serializes a container according to the `pretty_storage` setting and writes it to disk,
+/// gzip compressed when `compress_storage` is on. The file in the other format is removed
+/// afterwards, which migrates existing containers on their next write. With sharding
+/// enabled the modules are spread over shard files instead.
+fn write_container(container_name: &str, data: &[serde_json::Value]) -> Result<(), Response> {
+    check_container_name(container_name)?;
+    
+    if DRY_RUN.get() {
+        return Ok(());
+    }
+    
+    let data = if configuration::get_config().keep_sorted { sorted_by_id(data) } else { Cow::Borrowed(data) };
+    let data = data.as_ref();
+    
+    let manager = get_container_manager();
+    if manager.coalesce_window.get().is_some() {
+        let generation = manager.stage_write(container_name, data.to_vec());
+        STAGED_WRITES.with_borrow_mut(|staged| staged.push((container_name.to_string(), generation)));
+        return Ok(());
+    }
+    
+    store_container(container_name, data)
+}
+
+fn store_container(container_name: &str, data: &[serde_json::Value]) -> Result<(), Response> {
+    check_container_name(container_name)?;
+    
+    let config = configuration::get_config();
+    
+    if config.shards_per_container > 1 {
+        return write_sharded_container(container_name, data, config.shards_per_container);
+    }
+    
+    let jsonl_path = jsonl_container_path(container_name);
+    
+    if uses_jsonl(config) {
+        let mut lines = String::new();
+        for module in data {
+            lines.push_str(&serde_json::to_string(module).map_err(|_| Response::Error(ErrorCode::StorageError, "Failed to format data".to_string()))?);
+            lines.push('\n');
+        }
+        
+        write_file(&jsonl_path, lines.as_bytes()).map_err(|_| Response::Error(ErrorCode::StorageError, "Failed to write container file".to_string()))?;
+        
+        for path in [container_path(container_name), compressed_container_path(container_name)] {
+            if Path::new(&path).exists() {
+                let _ = fs::remove_file(&path);
+            }
+        }
+    } else {
+        let formatted_data = format_container(data)?;
+        write_storage_file(&container_path(container_name), &compressed_container_path(container_name), &formatted_data)?;
+        
+        if Path::new(&jsonl_path).exists() {
+            let _ = fs::remove_file(&jsonl_path);
+        }
+    }
+    
+    // Folds a previously sharded container back into a single file
+    let shard_dir = shard_directory(container_name);
+    if Path::new(&shard_dir).is_dir() {
+        let _ = fs::remove_dir_all(&shard_dir);
+        get_container_manager().forget_shard_digests(container_name);
+    }
+    
+    Ok(())
+}
+
+/// Creates the file of an empty container unless it already exists. Holding the
+/// container lock keeps INIT out until the file is complete, and `create_new`
+/// leaves a file alone that another process sharing the data_dir created first.
+fn create_empty_container(container_name: &str) -> Result<(), Response> {
+    check_container_name(container_name)?;
+    
+    let lock = get_container_manager().get_container_lock(container_name);
+    let _guard = lock.lock().unwrap();
+    
+    if container_exists(container_name) {
+        return Ok(());
+    }
+    
+    let config = configuration::get_config();
+    if config.shards_per_container > 1 || DRY_RUN.get() {
+        return write_container(container_name, &[]);
+    }
+    
+    let created = if uses_jsonl(config) {
+        create_file(&jsonl_container_path(container_name), b"")
+    } else if config.compress_storage {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(format_container::<serde_json::Value>(&[])?.as_bytes())
+            .and_then(|_| encoder.finish())
+            .and_then(|compressed| create_file(&compressed_container_path(container_name), &compressed))
+    } else {
+        create_file(&container_path(container_name), format_container::<serde_json::Value>(&[])?.as_bytes())
+    };
+    
+    created.map(|_| ()).map_err(|_| Response::Error(ErrorCode::StorageError, "Failed to write container file".to_string()))
+}
+
+/// Stores a container whose only change is a new module at the end of `data`.
+/// A JSONL container gets that module appended as one line instead of a rewrite.
+fn write_appended_container(container_name: &str, data: &[serde_json::Value]) -> Result<(), Response> {
+    check_container_name(container_name)?;
+    
+    let path = jsonl_container_path(container_name);
+    
+    let Some(module) = data.last() else {
+        return write_container(container_name, data);
+    };
+    
+    // A staged snapshot is newer than the file, so appending to the file would lose it
+    let coalescing = get_container_manager().coalesce_window.get().is_some();
+    let config = configuration::get_config();
+    if DRY_RUN.get() || coalescing || config.keep_sorted || !uses_jsonl(config) || !Path::new(&path).exists() {
+        return write_container(container_name, data);
+    }
+    
+    let mut line = serde_json::to_string(module).map_err(|_| Response::Error(ErrorCode::StorageError, "Failed to format data".to_string()))?;
+    line.push('\n');
+    
+    append_file(&path, line.as_bytes()).map_err(|_| Response::Error(ErrorCode::StorageError, "Failed to write container file".to_string()))
+}
+
+/// A container ordered by module id for `keep_sorted`, copied only when it is out of order.
+fn sorted_by_id(data: &[serde_json::Value]) -> Cow<'_, [serde_json::Value]> {
+    let id_field = &configuration::get_config().id_field;
+    if data.is_sorted_by_key(|item| module_id(item, id_field)) {
+        return Cow::Borrowed(data);
+    }
+    
+    let mut sorted = data.to_vec();
+    sorted.sort_by(|a, b| module_id(a, id_field).cmp(&module_id(b, id_field)));
+    Cow::Owned(sorted)
+}
+
+/// Whether single-file containers are stored as JSON lines.
+fn uses_jsonl(config: &configuration::Config) -> bool {
+    config.storage_format == StorageFormat::Jsonl && config.shards_per_container <= 1 && !config.compress_storage
+}
+
+/// Distributes modules over `shard_count` files by a hash of their id and only
+/// rewrites the shards whose content changed since they were last written.
+fn write_sharded_container(container_name: &str, data: &[serde_json::Value], shard_count: usize) -> Result<(), Response> {
+    let manager = get_container_manager();
+    let id_field = &configuration::get_config().id_field;
+    
+    let mut shards: Vec<Vec<&serde_json::Value>> = vec![Vec::new(); shard_count];
+    for item in data {
+        let shard = module_id(item, id_field)
+            .map(|id| (fnv1a(normalize_id(id).as_bytes()) % shard_count as u64) as usize)
+            .unwrap_or(0);
+        shards[shard].push(item);
+    }
+    
+    let shard_dir = shard_directory(container_name);
+    if !Path::new(&shard_dir).is_dir() {
+        // Removed while running, so none of the cached digests match the disk anymore
+        manager.forget_shard_digests(container_name);
+    }
+    fs::create_dir_all(&shard_dir).map_err(|_| Response::Error(ErrorCode::StorageError, "Failed to write container file".to_string()))?;
+    
+    for (shard, modules) in shards.iter().enumerate() {
+        let formatted_data = format_container(modules)?;
+        
+        if !manager.update_shard_digest(container_name, shard, fnv1a(formatted_data.as_bytes())) {
+            continue;
+        }
+        
+        if let Err(e) = write_storage_file(&shard_path(container_name, shard), &compressed_shard_path(container_name, shard), &formatted_data) {
+            manager.forget_shard_digests(container_name);
+            return Err(e);
+        }
+    }
+    
+    // Shards beyond the configured count are left over from an earlier setting
+    if let Ok(entries) = fs::read_dir(&shard_dir) {
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            if shard_number(&entry.file_name().to_string_lossy()).is_some_and(|shard| shard >= shard_count) {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+    }
+    
+    for path in [container_path(container_name), compressed_container_path(container_name), jsonl_container_path(container_name)] {
+        if Path::new(&path).exists() {
+            let _ = fs::remove_file(&path);
+        }
+    }
+    
+    Ok(())
+}
+
+fn format_container<T: serde::Serialize>(data: &[T]) -> Result<String, Response> {
+    let formatted_data = if configuration::get_config().pretty_storage {
+        serde_json::to_string_pretty(data)
+    } else {
+        serde_json::to_string(data)
+    };
+    
+    formatted_data.map_err(|_| Response::Error(ErrorCode::StorageError, "Failed to format data".to_string()))
+}
+
+/// Writes a storage file in the format selected by `compress_storage` and removes
+/// its counterpart in the other format.
+fn write_storage_file(plain_path: &str, compressed_path: &str, formatted_data: &str) -> Result<(), Response> {
+    let (target_path, stale_path) = if configuration::get_config().compress_storage {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        let compressed = encoder
+            .write_all(formatted_data.as_bytes())
+            .and_then(|_| encoder.finish())
+            .map_err(|_| Response::Error(ErrorCode::StorageError, "Failed to compress container file".to_string()))?;
+        
+        write_file(compressed_path, &compressed).map_err(|_| Response::Error(ErrorCode::StorageError, "Failed to write container file".to_string()))?;
+        (compressed_path, plain_path)
+    } else {
+        write_file(plain_path, formatted_data.as_bytes()).map_err(|_| Response::Error(ErrorCode::StorageError, "Failed to write container file".to_string()))?;
+        (plain_path, compressed_path)
+    };
+    
+    if target_path != stale_path && Path::new(stale_path).exists() {
+        let _ = fs::remove_file(stale_path);
+    }
+    
+    Ok(())
+}
+
+/// Writes a storage file, recreating its directory first if it was removed while
+/// the server was running.
+fn write_file(path: &str, contents: &[u8]) -> std::io::Result<()> {
+    match write_file_contents(path, contents) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            if let Some(directory) = Path::new(path).parent() {
+                fs::create_dir_all(directory)?;
+            }
+            write_file_contents(path, contents)
+        }
+        result => result,
+    }
+}
+
+/// Creates a file that must not exist yet. Returns `false` without touching it when
+/// another writer created the file first.
+fn create_file(path: &str, contents: &[u8]) -> std::io::Result<bool> {
+    let open = || fs::OpenOptions::new().write(true).create_new(true).open(path);
+    
+    let mut file = match open() {
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => return Ok(false),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            if let Some(directory) = Path::new(path).parent() {
+                fs::create_dir_all(directory)?;
+            }
+            match open() {
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => return Ok(false),
+                result => result?,
+            }
+        }
+        result => result?,
+    };
+    
+    file.write_all(contents)?;
+    
+    if configuration::get_config().fsync_on_write {
+        file.sync_all()?;
+    }
+    
+    Ok(true)
+}
+
+fn append_file(path: &str, contents: &[u8]) -> std::io::Result<()> {
+    let mut file = fs::OpenOptions::new().append(true).open(path)?;
+    file.write_all(contents)?;
+    
+    if configuration::get_config().fsync_on_write {
+        file.sync_all()?;
+    }
+    
+    Ok(())
+}
+
+/// Writes the file through a temp file that is renamed over it, so readers and
+/// crashes never see partial content. File and directory are synced to disk
+/// when `fsync_on_write` is on.
+fn write_file_contents(path: &str, contents: &[u8]) -> std::io::Result<()> {
+    let fsync = configuration::get_config().fsync_on_write;
+    
+    // Created next to the target, since rename is only atomic within one filesystem
+    let temp_path = temp_file_path(path);
+    let written = fs::File::create(&temp_path).and_then(|mut file| {
+        file.write_all(contents)?;
+        if fsync {
+            file.sync_all()?;
+        }
+        fs::rename(&temp_path, path)
+    });
+    
+    if let Err(e) = written {
+        let _ = fs::remove_file(&temp_path);
+        if e.kind() == std::io::ErrorKind::CrossesDevices {
+            return Err(std::io::Error::new(e.kind(), format!("Cannot atomically replace {}: its temp file is on another filesystem", path)));
+        }
+        return Err(e);
+    }
+    
+    if !fsync {
+        return Ok(());
+    }
+    
+    // The rename is only durable once the directory is synced as well
+    let directory = Path::new(path).parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    fs::File::open(directory)?.sync_all()
+}
+
+/// Unique temp file name in the directory of `path`.
+fn temp_file_path(path: &str) -> String {
+    format!("{}.{}.{}.tmp", path, std::process::id(), NEXT_TEMP_FILE.fetch_add(1, Ordering::Relaxed))
+}
+
+/// 64-bit FNV-1a, used where a hash has to stay stable across runs and builds.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3))
+}
+
+/// Whether `module_count` modules exceed the `max_modules_per_container` limit.
+fn exceeds_module_limit(module_count: usize) -> bool {
+    let limit = configuration::get_config().max_modules_per_container;
+    limit > 0 && module_count > limit
+}
+
+/// Runs a handler body on its own thread, carrying over the caller's dry-run flag.
+fn run_handler<F>(body: F) -> Response
+where
+    F: FnOnce() -> Response + Send,
+{
+    let dry_run = DRY_RUN.get();
+    
+    let (response, staged) = thread::scope(|s| {
+        s.spawn(move || {
+            DRY_RUN.set(dry_run);
+            let response = body();
+            (response, STAGED_WRITES.take())
+        }).join().unwrap_or_else(|_| (Response::Error(ErrorCode::Internal, "Thread panic".to_string()), Vec::new()))
+    });
+    
+    // Handed back to the caller, which awaits them after releasing its locks
+    STAGED_WRITES.with_borrow_mut(|tickets| tickets.extend(staged));
+    response
+}
+
+/// Bumps the version and records the write time on a module that is about to be
+/// persisted, as far as `track_versions` and `track_timestamps` ask for.
+fn touch_module(obj: &mut serde_json::Map<String, serde_json::Value>) {
+    let config = configuration::get_config();
+    
+    if config.track_versions {
+        let version = module_version_of(obj).saturating_add(1);
+        obj.insert(VERSION_FIELD.to_string(), serde_json::Value::from(version));
+    }
+    
+    if config.track_timestamps {
+        obj.insert(UPDATED_AT_FIELD.to_string(), serde_json::Value::from(now_millis()));
+    }
+}
+
+/// Fields the server currently writes on its own. Schemas do not have to describe them.
+fn maintained_fields() -> Vec<&'static str> {
+    let config = configuration::get_config();
+    let mut fields = vec![EXPIRES_AT_FIELD];
+    
+    if config.track_versions {
+        fields.push(VERSION_FIELD);
+    }
+    if config.track_timestamps {
+        fields.push(UPDATED_AT_FIELD);
+    }
+    
+    fields
+}
+
+/// The `_version` of a module, 0 for modules written before versions were kept.
+fn module_version_of(obj: &serde_json::Map<String, serde_json::Value>) -> u64 {
+    obj.get(VERSION_FIELD).and_then(|version| version.as_u64()).unwrap_or(0)
+}
+
+/// Rejects client writes to fields the server maintains itself.
+fn check_writable_key(key: &str) -> Result<(), Response> {
+    if key == VERSION_FIELD && configuration::get_config().track_versions {
+        return Err(Response::Error(ErrorCode::FieldNotAllowed, format!("Field '{}' is reserved", key)));
+    }
+    
+    Ok(())
+}
+
+/// Called after a client wrote `key` of a module. Writing the id field renames
+/// the module in place, which the container's module index cannot follow.
+fn forget_renamed_module(container_name: &str, key: &str) {
+    if key == configuration::get_config().id_field {
+        get_container_manager().invalidate_module_index(container_name);
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Drops expired modules from a container array and returns how many were removed.
+fn purge_expired(data: &mut Vec<serde_json::Value>) -> usize {
+    let now = now_millis();
+    let before = data.len();
+    data.retain(|item| !is_expired(item, now));
+    before - data.len()
+}
+
+/// Whether a module's `expires_at` has passed. Expired modules count as absent
+/// even before the sweeper removes them.
+fn is_expired(item: &serde_json::Value, now: u64) -> bool {
+    item.get(EXPIRES_AT_FIELD)
+        .and_then(|expires_at| expires_at.as_u64())
+        .is_some_and(|expires_at| expires_at <= now)
+}
+
+/// Renders a stored value for the wire: strings as-is, everything else as JSON text.
+fn value_to_string(value: &serde_json::Value) -> String {
+    if let Some(encoded) = binary_value(value) {
+        return encoded.to_string();
+    }
+    
+    match value {
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Returns the base64 payload of a value stored through SETBIN.
+fn binary_value(value: &serde_json::Value) -> Option<&str> {
+    let obj = value.as_object()?;
+    if obj.len() != 1 {
+        return None;
+    }
+    obj.get(BINARY_TAG)?.as_str()
+}
+
+fn find_module<'a>(container_name: &str, array: &'a [serde_json::Value], id_field: &str, module: &str) -> Option<&'a serde_json::Map<String, serde_json::Value>> {
+    let position = get_container_manager().module_position(container_name, array, id_field, module)?;
+    if is_expired(&array[position], now_millis()) {
+        return None;
+    }
+    array[position].as_object()
+}
+
+fn find_module_mut<'a>(container_name: &str, array: &'a mut [serde_json::Value], id_field: &str, module: &str) -> Option<&'a mut serde_json::Map<String, serde_json::Value>> {
+    let position = get_container_manager().module_position(container_name, array, id_field, module)?;
+    if is_expired(&array[position], now_millis()) {
+        return None;
+    }
+    array[position].as_object_mut()
+}
+
+fn module_id<'a>(item: &'a serde_json::Value, id_field: &str) -> Option<&'a str> {
+    item.as_object()?.get(id_field)?.as_str()
+}
+
+/// Canonical form of a module id for comparisons, lowercase when `case_insensitive_ids` is on.
+fn normalize_id(id: &str) -> Cow<'_, str> {
+    if configuration::get_config().case_insensitive_ids {
+        Cow::Owned(id.to_lowercase())
+    } else {
+        Cow::Borrowed(id)
+    }
+}
+
+fn idempotency_key(container_name: &str, module: &str, token: &str) -> IdempotencyKey {
+    (container_name.to_string(), normalize_id(module).into_owned(), token.to_string())
+}
+
+fn build_module_index(array: &[serde_json::Value], id_field: &str) -> ModuleIndex {
+    let mut positions = HashMap::with_capacity(array.len());
+    
+    for (position, item) in array.iter().enumerate() {
+        if let Some(id) = module_id(item, id_field) {
+            // Keep the first occurrence so lookups match a front-to-back scan
+            positions.entry(normalize_id(id).into_owned()).or_insert(position);
+        }
+    }
+    
+    ModuleIndex { len: array.len(), positions }
+}
+
+/// Substitutes the module id for every `id_field` placeholder of a template,
+/// including those in nested objects and arrays. All other values, such as
+/// numbers and arrays, are kept as the module's defaults.
+fn replace_placeholder(container: &mut serde_json::Value, id_field: &str, replacement_value: &str) {
+    match container {
+        serde_json::Value::Object(obj) => {
+            let mut new_obj = serde_json::Map::new();
+            for (key, val) in obj {
+                if key == id_field {
+                    new_obj.insert(key.clone(), serde_json::Value::String(replacement_value.to_string()));
+                } else {
+                    let mut new_val = val.clone();
+                    replace_placeholder(&mut new_val, id_field, replacement_value);
+                    new_obj.insert(key.clone(), new_val);
+                }
+            }
+            *container = serde_json::Value::Object(new_obj);
+        }
+        serde_json::Value::Array(arr) => {
+            let new_arr: Vec<serde_json::Value> = arr.iter()
+                .map(|v| {
+                    let mut new_v = v.clone();
+                    replace_placeholder(&mut new_v, id_field, replacement_value);
+                    new_v
+                })
+                .collect();
+            *container = serde_json::Value::Array(new_arr);
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn replace_placeholder_keeps_typed_defaults() {
+        let mut module = json!({ "id": "", "count": 0, "ratio": 1.5, "tags": [], "active": false });
+        replace_placeholder(&mut module, "id", "alice");
+        
+        assert_eq!(module, json!({ "id": "alice", "count": 0, "ratio": 1.5, "tags": [], "active": false }));
+    }
+
+    #[test]
+    fn replace_placeholder_substitutes_nested_ids() {
+        let mut module = json!({ "id": "", "owner": { "id": "" }, "items": [{ "id": "", "qty": 2 }, 7] });
+        replace_placeholder(&mut module, "id", "alice");
+        
+        assert_eq!(module, json!({ "id": "alice", "owner": { "id": "alice" }, "items": [{ "id": "alice", "qty": 2 }, 7] }));
+    }
+
+    #[test]
+    fn replace_placeholder_only_sets_existing_placeholders() {
+        let mut module = json!({ "name": "", "count": 3 });
+        replace_placeholder(&mut module, "id", "alice");
+        
+        assert_eq!(module, json!({ "name": "", "count": 3 }));
+    }
+
+    #[test]
+    fn create_file_lets_exactly_one_concurrent_creator_win() {
+        let directory = std::env::temp_dir().join(format!("triangular-create-file-{}", std::process::id()));
+        let path = directory.join("tree").join("race.json").to_string_lossy().into_owned();
+        let barrier = std::sync::Barrier::new(8);
+        
+        let created: Vec<(usize, bool)> = thread::scope(|s| {
+            let handles: Vec<_> = (0..8).map(|writer| {
+                let (path, barrier) = (&path, &barrier);
+                s.spawn(move || {
+                    barrier.wait();
+                    (writer, create_file(path, format!("writer {}", writer).as_bytes()).unwrap())
+                })
+            }).collect();
+            handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+        });
+        
+        let winners: Vec<usize> = created.into_iter().filter(|(_, won)| *won).map(|(writer, _)| writer).collect();
+        assert_eq!(winners.len(), 1);
+        assert_eq!(fs::read_to_string(&path).unwrap(), format!("writer {}", winners[0]));
+        
+        let _ = fs::remove_dir_all(directory);
+    }
+
+    #[test]
+    fn temp_files_are_created_next_to_their_target() {
+        let target = container_path("users");
+        let temp = temp_file_path(&target);
+        
+        assert_eq!(Path::new(&temp).parent(), Path::new(&target).parent());
+        assert_ne!(temp, temp_file_path(&target));
+    }
+
+    #[test]
+    fn write_file_contents_replaces_the_target_without_leaving_temp_files() {
+        let directory = std::env::temp_dir().join(format!("triangular-write-file-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("users.json").to_string_lossy().into_owned();
+        
+        write_file_contents(&path, b"[1]").unwrap();
+        write_file_contents(&path, b"[2]").unwrap();
+        
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[2]");
+        let files: Vec<_> = fs::read_dir(&directory).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+        assert_eq!(files, ["users.json"]);
+        
+        let _ = fs::remove_dir_all(directory);
     }
 }
 
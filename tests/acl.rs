@@ -0,0 +1,82 @@
+// Copyright (c) 2025, TheByteSlayer, Triangular
+// Stores structured Data in JSON Files and makes it accessible over TCP, written in Rust.
+
+#![cfg(unix)]
+
+mod common;
+
+use serde_json::json;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::thread;
+use triangular_database::api::ApiManager;
+use triangular_database::configuration::{self, Config};
+
+fn acl(writer_rights: &[&str]) -> HashMap<String, Vec<String>> {
+    HashMap::from([("writer".to_string(), writer_rights.iter().map(|right| right.to_string()).collect())])
+}
+
+/// Writes the configuration file RECONFIG reads, with the given rights for the `writer` token.
+fn write_config(data_dir: &Path, writer_rights: &[&str]) -> String {
+    let config = Config { silent: true, data_dir: data_dir.to_string_lossy().into_owned(), acl: acl(writer_rights), ..Config::default() };
+    let path = data_dir.join(configuration::DEFAULT_CONFIG_PATH);
+    fs::write(&path, toml::to_string(&config).unwrap()).unwrap();
+    path.to_string_lossy().into_owned()
+}
+
+/// Sends one request and reads its framed reply.
+fn request(stream: &mut UnixStream, reader: &mut BufReader<UnixStream>, line: &str) -> String {
+    stream.write_all(format!("{}\n", line).as_bytes()).unwrap();
+    
+    let mut reply = Vec::new();
+    loop {
+        let mut length = String::new();
+        reader.read_line(&mut length).unwrap();
+        let length: usize = length.trim_end().parse().unwrap();
+        if length == 0 {
+            return String::from_utf8(reply).unwrap();
+        }
+        
+        let start = reply.len();
+        reply.resize(start + length + 1, 0);
+        reader.read_exact(&mut reply[start..]).unwrap();
+        reply.pop();
+    }
+}
+
+#[test]
+fn exec_checks_queued_commands_against_the_current_acl() {
+    let data_dir = common::set_up(json!({ "users": { "id": "", "name": "" } }), Config { acl: acl(&["*"]), ..Config::default() });
+    Config::load_or_create_from(&write_config(data_dir, &["*"])).unwrap();
+    
+    let (mut stream, server) = UnixStream::pair().unwrap();
+    thread::spawn(move || ApiManager::handle_connection(server, true));
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    
+    stream.write_all(b"HELLO 4\n").unwrap();
+    let mut hello = String::new();
+    while hello != "0\n" {
+        hello.clear();
+        reader.read_line(&mut hello).unwrap();
+    }
+    
+    assert_eq!(request(&mut stream, &mut reader, "AUTH writer"), "AUTH OK");
+    assert_eq!(request(&mut stream, &mut reader, "INIT users alice"), "INIT alice in Container 'users'");
+    assert_eq!(request(&mut stream, &mut reader, "MULTI"), "MULTI");
+    assert_eq!(request(&mut stream, &mut reader, "SET users alice name Alice"), "QUEUED");
+    assert_eq!(request(&mut stream, &mut reader, "GET users alice id"), "QUEUED");
+    
+    // The writer loses its write rights while the transaction is open
+    write_config(data_dir, &["@read", "EXEC"]);
+    configuration::reload_config().unwrap();
+    
+    let forbidden = "ERROR[FORBIDDEN]: Forbidden";
+    assert_eq!(
+        request(&mut stream, &mut reader, "EXEC"),
+        format!("EXEC 2\n{}\n{}\n5\nalice", forbidden.len(), forbidden),
+    );
+    assert_eq!(common::module("users", "alice")["name"], "");
+}
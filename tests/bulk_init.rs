@@ -0,0 +1,50 @@
+// Copyright (c) 2025, TheByteSlayer, Triangular
+// Stores structured Data in JSON Files and makes it accessible over TCP, written in Rust.
+
+mod common;
+
+use serde_json::json;
+use triangular_database::configuration::Config;
+use triangular_database::tree::{self, ListOptions};
+
+fn set_up() {
+    common::set_up(json!({ "items": { "id": "", "name": "" } }), Config::default());
+}
+
+fn count_with_prefix(prefix: &str) -> usize {
+    let options = ListOptions { prefix: Some(prefix), ..ListOptions::default() };
+    tree::handle_list_modules("items", &options).to_wire().split(", ").filter(|id| !id.is_empty()).count()
+}
+
+#[test]
+fn skips_and_reports_ids_already_in_the_container() {
+    set_up();
+    common::ok(tree::handle_init("items", "old-a", &[("name", "Original")], None));
+    
+    let reply = common::ok(tree::handle_bulk_init("items", r#"[{"id":"old-a","name":"Replacement"},{"id":"old-b","name":"New"}]"#));
+    
+    assert_eq!(reply, "BULKINIT 1 in Container 'items', rejected duplicates: old-a");
+    assert_eq!(common::module("items", "old-a")["name"], "Original");
+    assert_eq!(common::module("items", "old-b")["name"], "New");
+    assert_eq!(count_with_prefix("old-"), 2);
+}
+
+#[test]
+fn keeps_the_first_of_an_id_repeated_in_the_payload() {
+    set_up();
+    
+    let reply = common::ok(tree::handle_bulk_init("items", r#"[{"id":"dup-c","name":"First"},{"id":"dup-d"},{"id":"dup-c","name":"Second"}]"#));
+    
+    assert_eq!(reply, "BULKINIT 2 in Container 'items', rejected duplicates: dup-c");
+    assert_eq!(common::module("items", "dup-c")["name"], "First");
+    assert_eq!(count_with_prefix("dup-"), 2);
+}
+
+#[test]
+fn reports_no_duplicates_when_every_id_is_new() {
+    set_up();
+    
+    let reply = common::ok(tree::handle_bulk_init("items", r#"[{"id":"fresh-e"},{"id":"fresh-f"}]"#));
+    
+    assert_eq!(reply, "BULKINIT 2 in Container 'items'");
+}
@@ -0,0 +1,47 @@
+// Copyright (c) 2025, TheByteSlayer, Triangular
+// Stores structured Data in JSON Files and makes it accessible over TCP, written in Rust.
+
+mod common;
+
+use serde_json::json;
+use triangular_database::configuration::Config;
+use triangular_database::response::{ErrorCode, Response};
+use triangular_database::tree::{self, ListOptions};
+
+fn set_up() {
+    common::set_up(
+        json!({ "users": { "id": "", "name": "" } }),
+        Config { case_insensitive_ids: true, ..Config::default() },
+    );
+}
+
+#[test]
+fn finds_a_mixed_case_init_by_its_lowercase_id() {
+    set_up();
+    
+    common::ok(tree::handle_init("users", "Bob@Example.com", &[("name", "Bob")], None));
+    
+    assert_eq!(common::ok(tree::handle_get("users", "bob@example.com", "name")), "Bob");
+    assert_eq!(common::ok(tree::handle_set("users", "BOB@EXAMPLE.COM", "name", "Robert")), "SET name Robert");
+    assert_eq!(common::ok(tree::handle_get("users", "bob@example.com", "name")), "Robert");
+}
+
+#[test]
+fn stores_ids_in_lowercase() {
+    set_up();
+    
+    common::ok(tree::handle_init("users", "Carol@Example.com", &[], None));
+    
+    assert_eq!(common::module("users", "carol@example.com")["id"], "carol@example.com");
+    let listed = tree::handle_list_modules("users", &ListOptions { prefix: Some("CAROL"), ..ListOptions::default() });
+    assert_eq!(listed, Response::List(vec!["carol@example.com".to_string()]));
+}
+
+#[test]
+fn treats_ids_differing_in_case_as_one_module() {
+    set_up();
+    
+    common::ok(tree::handle_init("users", "dave", &[], None));
+    
+    assert!(matches!(tree::handle_init("users", "DAVE", &[], None), Response::Error(ErrorCode::TargetExists, _)));
+}
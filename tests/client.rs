@@ -0,0 +1,89 @@
+// Copyright (c) 2025, TheByteSlayer, Triangular
+// Stores structured Data in JSON Files and makes it accessible over TCP, written in Rust.
+
+mod common;
+
+use serde_json::json;
+use std::time::{Duration, Instant};
+use triangular_database::client::{Client, ClientError};
+use triangular_database::configuration::Config;
+
+fn connect() -> Client {
+    // Every connection holds a worker thread while it is open
+    common::set_up(
+        json!({ "users": { "id": "", "name": "" }, "feed": { "id": "", "text": "" } }),
+        Config { compression_threshold_bytes: 64, worker_threads: 16, ..Config::default() },
+    );
+    Client::connect(common::server_address()).expect("failed to connect")
+}
+
+#[test]
+fn reads_replies_without_a_trailing_newline() {
+    let mut client = connect();
+    
+    assert_eq!(client.ping().unwrap(), "PONG");
+    client.init("users", "alice").unwrap();
+    assert_eq!(client.set("users", "alice", "name", "Alice").unwrap(), "SET name Alice");
+    assert_eq!(client.get("users", "alice", "name").unwrap(), "Alice");
+}
+
+#[test]
+fn keeps_multi_line_replies_whole() {
+    let mut client = connect();
+    
+    let help = client.command(&["HELP"]).unwrap();
+    assert!(help.lines().count() > 10);
+    assert!(help.starts_with("PING [message]\n"));
+    assert_eq!(client.ping().unwrap(), "PONG");
+}
+
+#[test]
+fn lists_modules_one_per_line() {
+    let mut client = connect();
+    client.init("users", "list_a").unwrap();
+    client.init("users", "list_b").unwrap();
+    
+    let modules = client.list_modules("users").unwrap();
+    assert!(modules.contains(&"list_a".to_string()));
+    assert!(modules.contains(&"list_b".to_string()));
+}
+
+#[test]
+fn turns_error_replies_into_server_errors() {
+    let mut client = connect();
+    
+    match client.get("users", "nobody", "name") {
+        Err(ClientError::Server { code, .. }) => assert_eq!(code, "MODULE_NOT_FOUND"),
+        other => panic!("expected a server error, got {:?}", other),
+    }
+}
+
+#[test]
+fn decompresses_large_replies() {
+    let mut client = connect();
+    client.init("users", "compressed").unwrap();
+    client.set("users", "compressed", "name", &"x".repeat(500)).unwrap();
+    
+    assert_eq!(client.command(&["COMPRESS", "ON"]).unwrap(), "COMPRESS ON");
+    assert_eq!(client.get("users", "compressed", "name").unwrap(), "x".repeat(500));
+    assert_eq!(client.ping().unwrap(), "PONG");
+}
+
+#[test]
+fn sets_events_aside_from_replies() {
+    let mut subscriber = connect();
+    let mut writer = connect();
+    subscriber.command(&["SUBSCRIBE", "feed"]).unwrap();
+    
+    writer.init("feed", "post").unwrap();
+    
+    // Events are forwarded by their own thread, so give the first one a moment
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let mut events = Vec::new();
+    while events.is_empty() && Instant::now() < deadline {
+        assert_eq!(subscriber.ping().unwrap(), "PONG");
+        events = subscriber.take_events();
+    }
+    
+    assert_eq!(events, vec!["EVENT feed INIT post".to_string()]);
+}
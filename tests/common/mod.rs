@@ -0,0 +1,75 @@
+// Copyright (c) 2025, TheByteSlayer, Triangular
+// Stores structured Data in JSON Files and makes it accessible over TCP, written in Rust.
+
+//! Helpers shared by the integration tests. The configuration is global to the
+//! process, so each test file runs all of its tests against one data directory.
+
+#![allow(dead_code)]
+
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::thread;
+use triangular_database::api;
+use triangular_database::configuration::{self, Config};
+use triangular_database::response::Response;
+use triangular_database::tree;
+
+static DATA_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+static SERVER_ADDRESS: OnceLock<SocketAddr> = OnceLock::new();
+
+/// Installs `config` on a fresh temporary data directory with `tree` as its
+/// tree.json and loads the containers. Only the first call in a test binary sets
+/// anything up; later calls return the same directory.
+pub fn set_up(tree: serde_json::Value, config: Config) -> &'static Path {
+    DATA_DIR.get_or_init(|| {
+        let name = std::env::current_exe()
+            .ok()
+            .and_then(|exe| exe.file_stem().map(|stem| stem.to_string_lossy().into_owned()))
+            .unwrap_or_default();
+        let data_dir = std::env::temp_dir().join(format!("triangular-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&data_dir);
+        fs::create_dir_all(&data_dir).expect("failed to create the test data directory");
+        fs::write(data_dir.join("tree.json"), tree.to_string()).expect("failed to write tree.json");
+        
+        configuration::set_config(Config {
+            silent: true,
+            data_dir: data_dir.to_string_lossy().into_owned(),
+            ..config
+        });
+        tree::initialize_tree().expect("failed to create the tree directory");
+        tree::initialize_containers(true).expect("failed to load the containers");
+        
+        data_dir
+    })
+}
+
+/// Address of a server running inside the test binary on an ephemeral port,
+/// started on first use. Call `set_up` before.
+pub fn server_address() -> SocketAddr {
+    *SERVER_ADDRESS.get_or_init(|| {
+        let config = Config { ip: "127.0.0.1".to_string(), port: 0, silent: true, ..Config::default() };
+        let (listener, address) = api::bind_server(&config).expect("failed to bind the test server");
+        
+        thread::spawn(move || {
+            let _ = api::serve(listener);
+        });
+        
+        address
+    })
+}
+
+/// The message of an `Ok` response, failing the test on anything else.
+pub fn ok(response: Response) -> String {
+    match response {
+        Response::Ok(message) => message,
+        other => panic!("expected OK, got {}", other.to_wire()),
+    }
+}
+
+/// A module as stored, read back through GETALL.
+pub fn module(container: &str, module: &str) -> serde_json::Value {
+    serde_json::from_str(&ok(tree::handle_get_all(container, module))).expect("GETALL did not return JSON")
+}
@@ -0,0 +1,181 @@
+// Copyright (c) 2025, TheByteSlayer, Triangular
+// Stores structured Data in JSON Files and makes it accessible over TCP, written in Rust.
+
+#![cfg(unix)]
+
+mod common;
+
+use serde_json::json;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::os::unix::net::UnixStream;
+use std::thread;
+use triangular_database::api::{self, ApiManager};
+use triangular_database::configuration::Config;
+
+fn set_up() {
+    common::set_up(
+        json!({ "users": { "id": "", "name": "" }, "feed": { "id": "", "text": "" } }),
+        Config::default(),
+    );
+}
+
+/// Sends `requests` over one in-memory connection, closes the client's write half
+/// and returns everything the server wrote back until it hung up.
+fn exchange(requests: &[u8]) -> String {
+    set_up();
+    let (client, server) = UnixStream::pair().unwrap();
+    let connection = thread::spawn(move || ApiManager::handle_connection(server, true));
+    
+    (&client).write_all(requests).unwrap();
+    client.shutdown(Shutdown::Write).unwrap();
+    
+    let mut replies = String::new();
+    (&client).read_to_string(&mut replies).unwrap();
+    connection.join().unwrap();
+    
+    replies
+}
+
+#[test]
+fn serves_a_socket_pair() {
+    assert_eq!(exchange(b"PING\n"), "PONG");
+}
+
+#[test]
+fn runs_requests_of_one_connection_in_order() {
+    let replies = exchange(b"INIT users alice\nSET users alice name Alice\nGET users alice name\n");
+    
+    assert_eq!(replies, "INIT alice in Container 'users'SET name AliceAlice");
+}
+
+/// A reply as framed from protocol version 4 on, in a single chunk.
+fn framed(reply: &str) -> String {
+    format!("{}\n{}\n0\n", reply.len(), reply)
+}
+
+#[test]
+fn frames_replies_after_hello_4() {
+    let replies = exchange(b"PING\nHELLO 4\nHELP PING\nPING\n");
+    
+    let expected = [
+        "PONG".to_string(),
+        framed("HELLO 4 json subscriptions compression jsonrpc"),
+        framed("PING [message]\nReplies PONG, or echoes the message."),
+        framed("PONG"),
+    ];
+    assert_eq!(replies, expected.concat());
+}
+
+#[test]
+fn counts_the_replies_of_exec() {
+    let replies = exchange(b"HELLO 4\nMULTI\nINIT users carol\nHELP PING\nGET users carol missing\nEXEC\n");
+    
+    let help = "PING [message]\nReplies PONG, or echoes the message.";
+    let exec = format!(
+        "EXEC 3\n31\nINIT carol in Container 'users'\n{}\n{}\n35\nERROR[KEY_NOT_FOUND]: Key not found",
+        help.len(),
+        help,
+    );
+    assert!(replies.ends_with(&framed(&exec)), "{}", replies);
+}
+
+#[derive(Debug, PartialEq)]
+enum Message {
+    Event(String),
+    Reply(String),
+}
+
+/// Reads the next EVENT line or framed reply from a connection after HELLO 4.
+fn read_message(reader: &mut impl BufRead) -> Message {
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    if line.starts_with("EVENT ") {
+        return Message::Event(line.trim_end().to_string());
+    }
+    
+    let mut reply = Vec::new();
+    while line.trim_end() != "0" {
+        let length: usize = line.trim_end().parse().expect("expected a chunk length");
+        let mut chunk = vec![0; length + 1];
+        reader.read_exact(&mut chunk).unwrap();
+        reply.extend_from_slice(&chunk[..length]);
+        
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+    }
+    
+    Message::Reply(String::from_utf8(reply).unwrap())
+}
+
+#[test]
+fn tells_events_apart_from_replies() {
+    set_up();
+    let (client, server) = UnixStream::pair().unwrap();
+    thread::spawn(move || ApiManager::handle_connection(server, true));
+    let mut reader = BufReader::new(&client);
+    
+    (&client).write_all(b"HELLO 4\nSUBSCRIBE feed\n").unwrap();
+    read_message(&mut reader);
+    assert_eq!(read_message(&mut reader), Message::Reply("SUBSCRIBE feed".to_string()));
+    
+    assert_eq!(exchange(b"INIT feed post\n"), "INIT post in Container 'feed'");
+    
+    // A reply that looks like an event, and has no newline of its own
+    (&client).write_all(b"PING EVENT feed INIT fake\n").unwrap();
+    // The event is forwarded by its own thread, so it may come before or after the reply
+    let messages = [read_message(&mut reader), read_message(&mut reader)];
+    assert!(messages.contains(&Message::Event("EVENT feed INIT post".to_string())), "{:?}", messages);
+    assert!(messages.contains(&Message::Reply("EVENT feed INIT fake".to_string())), "{:?}", messages);
+}
+
+#[test]
+fn rejects_invalid_utf8_without_closing() {
+    let replies = exchange(b"\xff\xfe\nPING\n");
+    
+    assert_eq!(replies, "ERROR[INVALID_DATA]: Invalid UTF-8PONG");
+}
+
+#[test]
+fn never_stores_a_value_with_invalid_utf8() {
+    exchange(b"INIT users dana\nSET users dana name Dana\n");
+    
+    // A lone continuation byte, an overlong encoding, a truncated sequence and a surrogate
+    for value in [&b"\x80"[..], b"\xc0\xaf", b"caf\xc3", b"\xed\xa0\x80"] {
+        let request = [&b"SET users dana name "[..], value, b"\n"].concat();
+        assert_eq!(exchange(&request), "ERROR[INVALID_DATA]: Invalid UTF-8");
+    }
+    
+    assert_eq!(common::module("users", "dana")["name"], "Dana");
+}
+
+#[test]
+fn bind_server_serves_on_the_port_it_reports() {
+    set_up();
+    let config = Config { ip: "127.0.0.1".to_string(), port: 0, silent: true, ..Config::default() };
+    let (listener, address) = api::bind_server(&config).unwrap();
+    assert_ne!(address.port(), 0);
+    
+    thread::spawn(move || {
+        let _ = api::serve(listener);
+    });
+    
+    let mut client = TcpStream::connect(address).unwrap();
+    client.write_all(b"PING\n").unwrap();
+    client.shutdown(Shutdown::Write).unwrap();
+    
+    let mut reply = String::new();
+    client.read_to_string(&mut reply).unwrap();
+    assert_eq!(reply, "PONG");
+}
+
+#[test]
+fn compresses_only_framed_replies() {
+    let replies = exchange(b"COMPRESS ON\nHELP PING\n");
+    
+    let expected = [
+        "ERROR[UNSUPPORTED_PROTOCOL]: COMPRESS requires protocol version 4",
+        "PING [message]\nReplies PONG, or echoes the message.",
+    ];
+    assert_eq!(replies, expected.concat());
+}
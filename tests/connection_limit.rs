@@ -0,0 +1,50 @@
+// Copyright (c) 2025, TheByteSlayer, Triangular
+// Stores structured Data in JSON Files and makes it accessible over TCP, written in Rust.
+
+mod common;
+
+use serde_json::json;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::{Duration, Instant};
+use triangular_database::configuration::Config;
+
+const MAX_CONNECTIONS: usize = 2;
+
+fn connect() -> TcpStream {
+    common::set_up(json!({}), Config { max_connections: MAX_CONNECTIONS, worker_threads: 4, ..Config::default() });
+    let stream = TcpStream::connect(common::server_address()).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    stream
+}
+
+/// The reply to a PING on `stream`, or what a rejected connection got instead.
+fn ping(mut stream: &TcpStream) -> String {
+    let _ = stream.write_all(b"PING\n");
+    
+    let mut reply = [0; 64];
+    let read = stream.read(&mut reply).unwrap_or(0);
+    String::from_utf8_lossy(&reply[..read]).into_owned()
+}
+
+#[test]
+fn rejects_connections_over_the_limit() {
+    let open: Vec<TcpStream> = (0..MAX_CONNECTIONS).map(|_| connect()).collect();
+    for stream in &open {
+        assert_eq!(ping(stream), "PONG");
+    }
+    
+    let mut rejected = connect();
+    let mut reply = String::new();
+    rejected.read_to_string(&mut reply).unwrap();
+    assert_eq!(reply, "ERROR[TOO_MANY_CONNECTIONS]: Too many connections");
+    
+    // The slot is given back once the server notices the close
+    drop(open);
+    let started = Instant::now();
+    while ping(&connect()) != "PONG" {
+        assert!(started.elapsed() < Duration::from_secs(5), "no connection was accepted after closing the others");
+        thread::sleep(Duration::from_millis(20));
+    }
+}
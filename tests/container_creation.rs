@@ -0,0 +1,35 @@
+// Copyright (c) 2025, TheByteSlayer, Triangular
+// Stores structured Data in JSON Files and makes it accessible over TCP, written in Rust.
+
+mod common;
+
+use serde_json::{Value, json};
+use std::sync::Barrier;
+use std::thread;
+use triangular_database::configuration::Config;
+use triangular_database::tree;
+
+const CONTAINERS: usize = 20;
+
+#[test]
+fn concurrent_creators_leave_every_container_intact() {
+    let tree_json: serde_json::Map<String, Value> = (0..CONTAINERS).map(|i| (format!("c{}", i), json!({ "id": "" }))).collect();
+    common::set_up(Value::Object(tree_json), Config::default());
+    common::ok(tree::handle_init("c0", "kept", &[], None));
+    
+    let barrier = Barrier::new(8);
+    thread::scope(|s| {
+        for _ in 0..8 {
+            s.spawn(|| {
+                barrier.wait();
+                tree::get_container_manager().create_containers(true, false).unwrap();
+            });
+        }
+    });
+    
+    for i in 0..CONTAINERS {
+        let dump: Value = serde_json::from_str(&tree::handle_dump(&format!("c{}", i)).to_wire()).unwrap();
+        assert!(dump.is_array(), "c{} is {}", i, dump);
+    }
+    assert_eq!(common::module("c0", "kept")["id"], "kept");
+}
@@ -0,0 +1,93 @@
+// Copyright (c) 2025, TheByteSlayer, Triangular
+// Stores structured Data in JSON Files and makes it accessible over TCP, written in Rust.
+
+mod common;
+
+use flate2::read::GzDecoder;
+use serde_json::{Value, json};
+use std::io::Read;
+use triangular_database::configuration::Config;
+use triangular_database::response::Response;
+use triangular_database::tree;
+
+fn set_up() {
+    common::set_up(
+        json!({ "books": { "id": "", "title": "" }, "notes": { "id": "", "text": "" } }),
+        Config::default(),
+    );
+    
+    // Every test fills the same modules, whichever runs first creates them
+    for (container, module) in [("books", "dune"), ("books", "emma"), ("notes", "first")] {
+        let _ = tree::handle_init(container, module, &[], None);
+    }
+}
+
+fn json_of(response: Response) -> Value {
+    serde_json::from_str(&response.to_wire()).expect("reply is not JSON")
+}
+
+#[test]
+fn dump_all_streams_every_container() {
+    set_up();
+    
+    let mut wire = Vec::new();
+    tree::handle_dump_all().write_to(&mut wire).unwrap();
+    let dump: Value = serde_json::from_slice(&wire).expect("DUMP is not JSON");
+    
+    let expected = json!({
+        "books": json_of(tree::handle_dump("books")),
+        "notes": json_of(tree::handle_dump("notes")),
+    });
+    assert_eq!(dump, expected);
+    assert_eq!(dump["books"].as_array().map(Vec::len), Some(2));
+}
+
+/// Joins the chunks of a framed reply, dropping the lengths and the closing `0` line.
+fn unframe(mut wire: &[u8]) -> Vec<u8> {
+    let mut body = Vec::new();
+    loop {
+        let line_end = wire.iter().position(|&byte| byte == b'\n').unwrap();
+        let length: usize = std::str::from_utf8(&wire[..line_end]).unwrap().parse().unwrap();
+        if length == 0 {
+            return body;
+        }
+        body.extend_from_slice(&wire[line_end + 1..line_end + 1 + length]);
+        wire = &wire[line_end + 2 + length..];
+    }
+}
+
+#[test]
+fn compresses_a_streamed_dump() {
+    set_up();
+    let response = tree::handle_dump_all();
+    
+    let mut wire = Vec::new();
+    response.write_framed_to(&mut wire, Some(16)).unwrap();
+    
+    let body = wire.strip_prefix(b"GZIP\n").expect("reply is not compressed");
+    let mut plain = String::new();
+    GzDecoder::new(&unframe(body)[..]).read_to_string(&mut plain).unwrap();
+    assert_eq!(plain, response.to_wire());
+}
+
+#[test]
+fn leaves_small_replies_uncompressed() {
+    let mut wire = Vec::new();
+    Response::Ok("PONG".to_string()).write_framed_to(&mut wire, Some(16)).unwrap();
+    
+    assert_eq!(wire, b"4\nPONG\n0\n");
+}
+
+#[test]
+fn writes_lists_item_by_item() {
+    set_up();
+    let options = tree::ListOptions::default();
+    
+    let mut joined = Vec::new();
+    tree::handle_list_modules("books", &options).write_to(&mut joined).unwrap();
+    assert_eq!(joined, b"dune, emma");
+    
+    let mut lines = Vec::new();
+    tree::handle_list_modules("books", &options).into_line_list().write_to(&mut lines).unwrap();
+    assert_eq!(lines, b"dune\nemma");
+}
@@ -0,0 +1,86 @@
+// Copyright (c) 2025, TheByteSlayer, Triangular
+// Stores structured Data in JSON Files and makes it accessible over TCP, written in Rust.
+
+mod common;
+
+use serde_json::json;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use triangular_database::api;
+use triangular_database::configuration::Config;
+use triangular_database::response::{ErrorCode, Response};
+
+fn set_up() {
+    common::set_up(
+        json!({ "sessions": { "id": "", "user": "" } }),
+        Config { expiry_sweep_interval_secs: 0, ..Config::default() },
+    );
+}
+
+fn run(request: &str) -> Response {
+    api::process_request(request, None)
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64
+}
+
+fn version(module: &str) -> u64 {
+    common::ok(run(&format!("VER sessions {}", module))).parse().unwrap()
+}
+
+#[test]
+fn expire_sets_expires_at_from_now() {
+    set_up();
+    common::ok(run("INIT sessions s1"));
+    
+    let before = now_millis();
+    assert_eq!(common::ok(run("EXPIRE sessions s1 60")), "EXPIRE s1 60");
+    
+    let expires_at = common::module("sessions", "s1")["expires_at"].as_u64().unwrap();
+    assert!((before + 60_000..=now_millis() + 60_000).contains(&expires_at), "expires_at {}", expires_at);
+}
+
+#[test]
+fn persist_removes_expires_at() {
+    set_up();
+    common::ok(run("INIT sessions s2 TTL 60"));
+    assert!(common::module("sessions", "s2").get("expires_at").is_some());
+    
+    assert_eq!(common::ok(run("PERSIST sessions s2")), "PERSIST s2");
+    
+    assert!(common::module("sessions", "s2").get("expires_at").is_none());
+}
+
+#[test]
+fn an_expired_module_is_hidden_from_get() {
+    set_up();
+    common::ok(run("INIT sessions s3 user=alice"));
+    common::ok(run("EXPIRE sessions s3 1"));
+    assert_eq!(common::ok(run("GET sessions s3 user")), "alice");
+    
+    thread::sleep(Duration::from_millis(1100));
+    
+    assert!(matches!(run("GET sessions s3 user"), Response::Error(ErrorCode::ModuleNotFound, _)));
+}
+
+#[test]
+fn expire_and_persist_bump_the_version() {
+    set_up();
+    common::ok(run("INIT sessions s4"));
+    let initial = version("s4");
+    
+    common::ok(run("EXPIRE sessions s4 60"));
+    assert_eq!(version("s4"), initial + 1);
+    
+    common::ok(run("PERSIST sessions s4"));
+    assert_eq!(version("s4"), initial + 2);
+}
+
+#[test]
+fn an_unknown_module_is_an_error() {
+    set_up();
+    
+    assert!(matches!(run("EXPIRE sessions missing 60"), Response::Error(ErrorCode::ModuleNotFound, _)));
+    assert!(matches!(run("PERSIST sessions missing"), Response::Error(ErrorCode::ModuleNotFound, _)));
+}
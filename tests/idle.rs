@@ -0,0 +1,69 @@
+// Copyright (c) 2025, TheByteSlayer, Triangular
+// Stores structured Data in JSON Files and makes it accessible over TCP, written in Rust.
+
+#![cfg(unix)]
+
+mod common;
+
+use serde_json::json;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::thread;
+use std::time::{Duration, Instant};
+use triangular_database::api::ApiManager;
+use triangular_database::configuration::Config;
+
+fn connect() -> (UnixStream, thread::JoinHandle<()>) {
+    common::set_up(json!({ "users": { "id": "" } }), Config { idle_timeout_secs: 1, ..Config::default() });
+    let (client, server) = UnixStream::pair().unwrap();
+    let connection = thread::spawn(move || ApiManager::handle_connection(server, true));
+    
+    (client, connection)
+}
+
+#[test]
+fn closes_a_silent_connection() {
+    let (mut client, connection) = connect();
+    let started = Instant::now();
+    
+    let mut rest = Vec::new();
+    client.read_to_end(&mut rest).unwrap();
+    connection.join().unwrap();
+    
+    assert!(started.elapsed() < Duration::from_secs(3));
+}
+
+#[test]
+fn closes_a_connection_that_never_finishes_a_request() {
+    let (client, connection) = connect();
+    let started = Instant::now();
+    
+    // Each byte arrives well within the timeout, but no request is ever completed
+    let mut trickle = client.try_clone().unwrap();
+    thread::spawn(move || {
+        while started.elapsed() < Duration::from_secs(10) && trickle.write_all(b"P").is_ok() {
+            thread::sleep(Duration::from_millis(100));
+        }
+    });
+    
+    connection.join().unwrap();
+    
+    assert!(started.elapsed() < Duration::from_secs(3), "connection stayed open for {:?}", started.elapsed());
+}
+
+#[test]
+fn keeps_an_active_connection_open() {
+    let (mut client, connection) = connect();
+    
+    for _ in 0..3 {
+        thread::sleep(Duration::from_millis(500));
+        client.write_all(b"PING\n").unwrap();
+        
+        let mut reply = [0; 4];
+        client.read_exact(&mut reply).unwrap();
+        assert_eq!(&reply, b"PONG");
+    }
+    
+    drop(client);
+    connection.join().unwrap();
+}
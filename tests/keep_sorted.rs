@@ -0,0 +1,33 @@
+// Copyright (c) 2025, TheByteSlayer, Triangular
+// Stores structured Data in JSON Files and makes it accessible over TCP, written in Rust.
+
+mod common;
+
+use serde_json::{Value, json};
+use std::fs;
+use triangular_database::configuration::Config;
+use triangular_database::response::Response;
+use triangular_database::tree::{self, ListOptions};
+
+#[test]
+fn stores_modules_sorted_by_id_after_out_of_order_inits() {
+    let data_dir = common::set_up(json!({ "teams": { "id": "", "name": "" } }), Config { keep_sorted: true, ..Config::default() });
+    
+    for id in ["charlie", "alpha", "delta", "bravo"] {
+        common::ok(tree::handle_init("teams", id, &[("name", id)], None));
+    }
+    common::ok(tree::handle_set("teams", "alpha", "name", "Alpha"));
+    
+    let sorted = ["alpha", "bravo", "charlie", "delta"];
+    assert_eq!(
+        tree::handle_list_modules("teams", &ListOptions::default()),
+        Response::List(sorted.iter().map(|id| id.to_string()).collect()),
+    );
+    
+    let stored: Vec<Value> = serde_json::from_str(&fs::read_to_string(data_dir.join("tree").join("teams.json")).unwrap()).unwrap();
+    let stored_ids: Vec<&str> = stored.iter().filter_map(|module| module["id"].as_str()).collect();
+    assert_eq!(stored_ids, sorted);
+    
+    assert_eq!(common::ok(tree::handle_get("teams", "alpha", "name")), "Alpha");
+    assert_eq!(common::ok(tree::handle_get("teams", "delta", "name")), "delta");
+}
@@ -0,0 +1,82 @@
+// Copyright (c) 2025, TheByteSlayer, Triangular
+// Stores structured Data in JSON Files and makes it accessible over TCP, written in Rust.
+
+mod common;
+
+use serde_json::json;
+use std::collections::HashSet;
+use std::sync::{Arc, mpsc};
+use std::thread;
+use std::time::Duration;
+use triangular_database::configuration::Config;
+use triangular_database::response::Response;
+use triangular_database::tree::{self, ListOptions};
+
+const MODULES: usize = 10;
+const ROUNDS: usize = 20;
+
+fn set_up() {
+    common::set_up(
+        json!({ "left": { "id": "" }, "right": { "id": "" }, "first": { "id": "" }, "second": { "id": "" } }),
+        Config::default(),
+    );
+}
+
+fn modules_of(container: &str) -> Vec<String> {
+    match tree::handle_list_modules(container, &ListOptions::default()) {
+        Response::List(modules) => modules,
+        other => panic!("LIST {} failed: {}", container, other.to_wire()),
+    }
+}
+
+/// Moves `modules` from `from` to `to` and back, `ROUNDS` times.
+fn shuttle(modules: Vec<String>, from: &'static str, to: &'static str) {
+    for _ in 0..ROUNDS {
+        for (source, target) in [(from, to), (to, from)] {
+            for module in &modules {
+                common::ok(tree::handle_move(source, module, target));
+            }
+        }
+    }
+}
+
+#[test]
+fn opposite_moves_never_deadlock() {
+    set_up();
+    let left: Vec<String> = (0..MODULES).map(|i| format!("l{}", i)).collect();
+    let right: Vec<String> = (0..MODULES).map(|i| format!("r{}", i)).collect();
+    for module in &left {
+        common::ok(tree::handle_init("left", module, &[], None));
+    }
+    for module in &right {
+        common::ok(tree::handle_init("right", module, &[], None));
+    }
+    
+    let (done, finished) = mpsc::channel();
+    for (modules, from, to) in [(left.clone(), "left", "right"), (right.clone(), "right", "left")] {
+        let done = done.clone();
+        thread::spawn(move || {
+            shuttle(modules, from, to);
+            done.send(()).unwrap();
+        });
+    }
+    
+    for _ in 0..2 {
+        finished.recv_timeout(Duration::from_secs(60)).expect("moves in opposite directions deadlocked");
+    }
+    
+    assert_eq!(modules_of("left").into_iter().collect::<HashSet<_>>(), left.into_iter().collect());
+    assert_eq!(modules_of("right").into_iter().collect::<HashSet<_>>(), right.into_iter().collect());
+}
+
+#[test]
+fn container_locks_come_in_name_order_without_duplicates() {
+    set_up();
+    let manager = tree::get_container_manager();
+    
+    let locks = manager.get_container_locks(&["second", "first", "second"]);
+    
+    assert_eq!(locks.len(), 2);
+    assert!(Arc::ptr_eq(&locks[0], &manager.get_container_lock("first")));
+    assert!(Arc::ptr_eq(&locks[1], &manager.get_container_lock("second")));
+}
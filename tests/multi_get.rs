@@ -0,0 +1,52 @@
+// Copyright (c) 2025, TheByteSlayer, Triangular
+// Stores structured Data in JSON Files and makes it accessible over TCP, written in Rust.
+
+mod common;
+
+use serde_json::{Value, json};
+use triangular_database::configuration::Config;
+use triangular_database::response::Response;
+use triangular_database::tree;
+
+fn set_up() {
+    common::set_up(json!({ "users": { "id": "", "name": "" } }), Config::default());
+}
+
+#[test]
+fn mgetall_answers_one_line_per_requested_module_in_order() {
+    set_up();
+    common::ok(tree::handle_init("users", "ann", &[("name", "Ann, Jr.")], None));
+    common::ok(tree::handle_init("users", "ben", &[("name", "Ben")], None));
+    
+    let Response::Lines(lines) = tree::handle_mget_all("users", &["ben", "nobody", "ann"]) else {
+        panic!("MGETALL did not answer with lines");
+    };
+    
+    assert_eq!(lines.len(), 3);
+    assert_eq!(serde_json::from_str::<Value>(&lines[0]).unwrap(), common::module("users", "ben"));
+    assert_eq!(lines[1], "(nil)");
+    assert_eq!(serde_json::from_str::<Value>(&lines[2]).unwrap(), common::module("users", "ann"));
+}
+
+#[test]
+fn mget_lists_values_in_the_order_of_the_keys() {
+    set_up();
+    common::ok(tree::handle_init("users", "cid", &[("name", "Cid")], None));
+    
+    let response = tree::handle_mget("users", "cid", &["name", "missing", "id"]);
+    
+    assert_eq!(response.to_wire(), "Cid, (nil), cid");
+    assert_eq!(response.into_line_list().to_wire(), "Cid\n(nil)\ncid");
+}
+
+#[test]
+fn pluck_lists_one_item_per_module() {
+    set_up();
+    common::ok(tree::handle_init("users", "dee", &[("name", "Dee")], None));
+    
+    let Response::List(items) = tree::handle_pluck("users", "name") else {
+        panic!("PLUCK did not answer with a list");
+    };
+    
+    assert!(items.contains(&"dee=Dee".to_string()), "{:?}", items);
+}
@@ -0,0 +1,46 @@
+// Copyright (c) 2025, TheByteSlayer, Triangular
+// Stores structured Data in JSON Files and makes it accessible over TCP, written in Rust.
+
+mod common;
+
+use serde_json::{Value, json};
+use triangular_database::configuration::Config;
+use triangular_database::response::Response;
+use triangular_database::tree;
+
+fn set_up() {
+    common::set_up(json!({ "users": { "id": "", "name": "", "profile": {} } }), Config::default());
+}
+
+#[test]
+fn get_returns_an_object_as_json() {
+    set_up();
+    common::ok(tree::handle_init("users", "alice", &[], None));
+    
+    let profile = json!({ "city": "Oslo", "tags": ["admin", "ops"], "limits": { "daily": 5 } });
+    common::ok(tree::handle_set_json("users", "alice", "profile", &profile.to_string()));
+    
+    let response = tree::handle_get("users", "alice", "profile");
+    assert_eq!(response, Response::Json(profile.clone()));
+    assert_eq!(serde_json::from_str::<Value>(&response.to_wire()).unwrap(), profile);
+}
+
+#[test]
+fn get_returns_an_array_as_json() {
+    set_up();
+    common::ok(tree::handle_init("users", "bob", &[], None));
+    common::ok(tree::handle_set_json("users", "bob", "name", r#"["Bob", "Robert"]"#));
+    
+    let wire = tree::handle_get("users", "bob", "name").to_wire();
+    assert_eq!(serde_json::from_str::<Value>(&wire).unwrap(), json!(["Bob", "Robert"]));
+}
+
+#[test]
+fn get_keeps_strings_and_binary_values_as_text() {
+    set_up();
+    common::ok(tree::handle_init("users", "carol", &[("name", "Carol")], None));
+    common::ok(tree::handle_set_binary("users", "carol", "profile", "AAEC"));
+    
+    assert_eq!(tree::handle_get("users", "carol", "name"), Response::Ok("Carol".to_string()));
+    assert_eq!(tree::handle_get("users", "carol", "profile"), Response::Ok("AAEC".to_string()));
+}
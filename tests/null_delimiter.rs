@@ -0,0 +1,63 @@
+// Copyright (c) 2025, TheByteSlayer, Triangular
+// Stores structured Data in JSON Files and makes it accessible over TCP, written in Rust.
+
+#![cfg(unix)]
+
+mod common;
+
+use serde_json::json;
+use std::io::{Read, Write};
+use std::net::Shutdown;
+use std::os::unix::net::UnixStream;
+use std::thread;
+use triangular_database::api::ApiManager;
+use triangular_database::configuration::{Config, RequestDelimiter};
+
+/// Sends NUL delimited `requests` over one in-memory connection and returns
+/// everything the server wrote back.
+fn exchange(requests: &[&str]) -> String {
+    common::set_up(
+        json!({ "notes": { "id": "", "title": "", "body": "" } }),
+        Config { request_delimiter: RequestDelimiter::Null, ..Config::default() },
+    );
+    let (client, server) = UnixStream::pair().unwrap();
+    let connection = thread::spawn(move || ApiManager::handle_connection(server, true));
+    
+    for request in requests {
+        (&client).write_all(request.as_bytes()).unwrap();
+        (&client).write_all(b"\0").unwrap();
+    }
+    client.shutdown(Shutdown::Write).unwrap();
+    
+    let mut replies = String::new();
+    (&client).read_to_string(&mut replies).unwrap();
+    connection.join().unwrap();
+    
+    replies
+}
+
+#[test]
+fn keeps_newlines_in_init_fields() {
+    exchange(&["INIT notes draft title=first\nsecond body=a\tb"]);
+    
+    let module = common::module("notes", "draft");
+    assert_eq!(module["title"], "first\nsecond");
+    assert_eq!(module["body"], "a\tb");
+}
+
+#[test]
+fn keeps_newlines_in_setm_values() {
+    exchange(&["INIT notes list", "SETM notes list title one\ntwo body three\n"]);
+    
+    let module = common::module("notes", "list");
+    assert_eq!(module["title"], "one\ntwo");
+    assert_eq!(module["body"], "three\n");
+}
+
+#[test]
+fn keeps_newlines_in_cas_values() {
+    let replies = exchange(&["INIT notes swap", "SET notes swap body old\nline", "CAS notes swap body old\nline new line\nhere"]);
+    
+    assert!(!replies.contains("ERROR"), "{}", replies);
+    assert_eq!(common::module("notes", "swap")["body"], "new line\nhere");
+}
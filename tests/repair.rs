@@ -0,0 +1,72 @@
+// Copyright (c) 2025, TheByteSlayer, Triangular
+// Stores structured Data in JSON Files and makes it accessible over TCP, written in Rust.
+
+mod common;
+
+use serde_json::json;
+use std::fs;
+use std::path::PathBuf;
+use triangular_database::configuration::Config;
+use triangular_database::response::{ErrorCode, Response};
+use triangular_database::tree::{self, Comparison, ListOptions};
+
+type Handler = Box<dyn Fn() -> Response>;
+
+const STRAY_MODULE: &str = r#"{"id":"solo","name":"Solo"}"#;
+
+/// Overwrites a container file with a single module object instead of an array.
+fn object_shaped(container: &str) -> PathBuf {
+    let data_dir = common::set_up(
+        json!({ "broken": { "id": "", "name": "", "count": 0 }, "stray": { "id": "", "name": "" }, "target": { "id": "", "name": "" } }),
+        Config::default(),
+    );
+    
+    let path = data_dir.join("tree").join(format!("{}.json", container));
+    fs::write(&path, STRAY_MODULE).unwrap();
+    path
+}
+
+#[test]
+fn every_handler_reports_an_object_shaped_container() {
+    let path = object_shaped("broken");
+    let not_an_array = Response::Error(ErrorCode::CorruptData, "Container is not an array".to_string());
+    
+    let handlers: Vec<(&str, Handler)> = vec![
+        ("INIT", Box::new(|| tree::handle_init("broken", "new", &[], None))),
+        ("INITJSON", Box::new(|| tree::handle_init_json("broken", r#"{"id":"new"}"#))),
+        ("BULKINIT", Box::new(|| tree::handle_bulk_init("broken", r#"[{"id":"new"}]"#))),
+        ("SET", Box::new(|| tree::handle_set("broken", "solo", "name", "Other"))),
+        ("SETJSON", Box::new(|| tree::handle_set_json("broken", "solo", "name", r#""Other""#))),
+        ("SETM", Box::new(|| tree::handle_set_many("broken", "solo", &[("name", "Other")], false))),
+        ("SETNX", Box::new(|| tree::handle_setnx("broken", "solo", "name", "Other"))),
+        ("CAS", Box::new(|| tree::handle_cas("broken", "solo", "name", "Solo", "Other"))),
+        ("DECR", Box::new(|| tree::handle_decr("broken", "solo", "count", 1))),
+        ("EXPIRE", Box::new(|| tree::handle_expire("broken", "solo", Some(60)))),
+        ("GET", Box::new(|| tree::handle_get("broken", "solo", "name"))),
+        ("MGET", Box::new(|| tree::handle_mget("broken", "solo", &["name"]))),
+        ("GETALL", Box::new(|| tree::handle_get_all("broken", "solo"))),
+        ("MGETALL", Box::new(|| tree::handle_mget_all("broken", &["solo"]))),
+        ("VER", Box::new(|| tree::handle_module_version("broken", "solo"))),
+        ("LIST", Box::new(|| tree::handle_list_modules("broken", &ListOptions::default()))),
+        ("LIST keys", Box::new(|| tree::handle_list_keys("broken", "solo", false))),
+        ("FIND", Box::new(|| tree::handle_find("broken", "name", Comparison::Eq, "Solo"))),
+        ("PLUCK", Box::new(|| tree::handle_pluck("broken", "name"))),
+        ("DUMP", Box::new(|| tree::handle_dump("broken"))),
+        ("MOVE", Box::new(|| tree::handle_move("broken", "solo", "target"))),
+        ("COMPACT", Box::new(|| tree::handle_compact("broken"))),
+    ];
+    
+    for (command, handler) in handlers {
+        assert_eq!(handler(), not_an_array, "{}", command);
+    }
+    assert_eq!(fs::read_to_string(path).unwrap(), STRAY_MODULE);
+}
+
+#[test]
+fn repair_wraps_the_object_into_an_array() {
+    object_shaped("stray");
+    
+    assert_eq!(common::ok(tree::handle_repair("stray")), "REPAIR Container 'stray' wrapped into an array");
+    assert_eq!(common::ok(tree::handle_get("stray", "solo", "name")), "Solo");
+    assert_eq!(common::ok(tree::handle_repair("stray")), "REPAIR Container 'stray' is already valid");
+}
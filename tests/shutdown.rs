@@ -0,0 +1,33 @@
+// Copyright (c) 2025, TheByteSlayer, Triangular
+// Stores structured Data in JSON Files and makes it accessible over TCP, written in Rust.
+
+mod common;
+
+use serde_json::{Value, json};
+use std::fs;
+use std::path::Path;
+use triangular_database::api;
+use triangular_database::configuration::Config;
+use triangular_database::tree;
+
+fn stored_name(data_dir: &Path) -> Value {
+    let content = fs::read_to_string(data_dir.join("tree").join("users.json")).unwrap();
+    let modules: Vec<Value> = serde_json::from_str(&content).unwrap();
+    modules.into_iter().find(|module| module["id"] == "alice").map_or(Value::Null, |module| module["name"].clone())
+}
+
+#[test]
+fn shutdown_drain_writes_staged_containers() {
+    let data_dir = common::set_up(json!({ "users": { "id": "", "name": "" } }), Config { coalesce_writes_ms: 500, ..Config::default() });
+    common::ok(tree::handle_init("users", "alice", &[], None));
+    tree::flush_staged_writes();
+    
+    // Called directly, the handler stages the write without waiting for it
+    common::ok(tree::handle_set("users", "alice", "name", "Alice"));
+    assert_eq!(stored_name(data_dir), "");
+    
+    let exclusive = api::drain_for_shutdown();
+    
+    assert_eq!(stored_name(data_dir), "Alice");
+    drop(exclusive);
+}
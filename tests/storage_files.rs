@@ -0,0 +1,38 @@
+// Copyright (c) 2025, TheByteSlayer, Triangular
+// Stores structured Data in JSON Files and makes it accessible over TCP, written in Rust.
+
+mod common;
+
+use serde_json::json;
+use std::fs;
+use std::path::Path;
+use triangular_database::configuration::Config;
+use triangular_database::response::{ErrorCode, Response};
+use triangular_database::tree;
+
+fn set_up() -> &'static Path {
+    common::set_up(json!({ "users": { "id": "", "name": "" } }), Config { fsync_on_write: true, ..Config::default() })
+}
+
+#[test]
+fn writes_only_the_container_file_into_the_data_dir() {
+    let data_dir = set_up();
+    
+    common::ok(tree::handle_init("users", "alice", &[], None));
+    common::ok(tree::handle_set("users", "alice", "name", "Alice"));
+    
+    let files: Vec<_> = fs::read_dir(data_dir.join("tree")).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+    assert_eq!(files, ["users.json"]);
+    assert_eq!(common::module("users", "alice")["name"], "Alice");
+}
+
+#[test]
+fn size_rejects_names_outside_the_tree_directory() {
+    set_up();
+    
+    for name in ["../tree.json", "../../etc/passwd", "a/b", ".."] {
+        let response = tree::handle_size(Some(name));
+        assert!(matches!(response, Response::Error(ErrorCode::InvalidName, _)), "SIZE {} returned {}", name, response.to_wire());
+    }
+    assert_eq!(tree::handle_size(Some("../tree.json")).to_wire(), "ERROR[INVALID_NAME]: Invalid container name '../tree.json'");
+}
@@ -0,0 +1,55 @@
+// Copyright (c) 2025, TheByteSlayer, Triangular
+// Stores structured Data in JSON Files and makes it accessible over TCP, written in Rust.
+
+mod common;
+
+use serde_json::json;
+use triangular_database::configuration::Config;
+use triangular_database::response::{ErrorCode, Response};
+use triangular_database::tree;
+
+fn set_up() {
+    common::set_up(
+        json!({
+            "typed": { "id": "", "count": 0, "ratio": 0.5, "tags": [], "flags": [1, 2], "active": false },
+            "nested": { "id": "", "owner": { "id": "", "level": 3 }, "items": [{ "id": "" }] },
+            "tags": "",
+        }),
+        Config::default(),
+    );
+}
+
+#[test]
+fn init_keeps_numbers_and_arrays_from_the_template() {
+    set_up();
+    common::ok(tree::handle_init("typed", "alice", &[], None));
+    
+    let module = common::module("typed", "alice");
+    assert_eq!(module["count"], json!(0));
+    assert_eq!(module["ratio"], json!(0.5));
+    assert_eq!(module["tags"], json!([]));
+    assert_eq!(module["flags"], json!([1, 2]));
+    assert_eq!(module["active"], json!(false));
+}
+
+#[test]
+fn init_fills_nested_id_placeholders() {
+    set_up();
+    common::ok(tree::handle_init("nested", "bob", &[], None));
+    
+    let module = common::module("nested", "bob");
+    assert_eq!(module["id"], json!("bob"));
+    assert_eq!(module["owner"], json!({ "id": "bob", "level": 3 }));
+    assert_eq!(module["items"], json!([{ "id": "bob" }]));
+}
+
+#[test]
+fn init_rejects_fields_for_a_template_that_is_not_an_object() {
+    set_up();
+    
+    let Response::Error(code, _) = tree::handle_init("tags", "red", &[("name", "Red")], None) else {
+        panic!("INIT stored fields it had to drop");
+    };
+    assert_eq!(code, ErrorCode::InvalidValue);
+    assert!(!tree::handle_dump("tags").to_wire().contains("red"));
+}
@@ -0,0 +1,38 @@
+// Copyright (c) 2025, TheByteSlayer, Triangular
+// Stores structured Data in JSON Files and makes it accessible over TCP, written in Rust.
+
+mod common;
+
+use serde_json::json;
+use std::fs;
+use std::path::Path;
+use triangular_database::configuration::Config;
+use triangular_database::response::{ErrorCode, Response};
+use triangular_database::tree;
+
+fn set_up() -> &'static Path {
+    common::set_up(
+        json!({ "_meta": { "version": 2, "note": "schema notes" }, "users": { "id": "" } }),
+        Config::default(),
+    )
+}
+
+#[test]
+fn meta_entry_is_not_a_container() {
+    let data_dir = set_up();
+    
+    assert_eq!(tree::handle_list_containers(), Response::List(vec!["users".to_string()]));
+    assert!(!data_dir.join("tree").join("_meta.json").exists());
+    assert!(tree::get_container_manager().get_template("_meta").is_none());
+}
+
+#[test]
+fn registering_a_meta_container_is_rejected() {
+    let data_dir = set_up();
+    
+    let registered = tree::get_container_manager().register_container("_meta", json!({ "id": "" }));
+    assert!(matches!(registered, Err(Response::Error(ErrorCode::InvalidName, _))));
+    
+    let tree_json: serde_json::Value = serde_json::from_str(&fs::read_to_string(data_dir.join("tree.json")).unwrap()).unwrap();
+    assert_eq!(tree_json["_meta"], json!({ "version": 2, "note": "schema notes" }));
+}
@@ -0,0 +1,62 @@
+// Copyright (c) 2025, TheByteSlayer, Triangular
+// Stores structured Data in JSON Files and makes it accessible over TCP, written in Rust.
+
+mod common;
+
+use serde_json::json;
+use triangular_database::api;
+use triangular_database::configuration::{Config, InitMode};
+use triangular_database::response::{ErrorCode, Response};
+use triangular_database::tree;
+
+fn set_up() {
+    common::set_up(
+        json!({ "users": { "id": "", "name": "" } }),
+        Config { init_mode: InitMode::Replace, ..Config::default() },
+    );
+}
+
+fn version(module: &str) -> u64 {
+    common::ok(tree::handle_module_version("users", module)).parse().unwrap()
+}
+
+#[test]
+fn clients_cannot_set_the_version() {
+    set_up();
+    common::ok(tree::handle_init("users", "alice", &[], None));
+    let before = version("alice");
+    
+    assert!(matches!(tree::handle_set("users", "alice", "_version", "99"), Response::Error(ErrorCode::FieldNotAllowed, _)));
+    assert!(matches!(tree::handle_set_json("users", "alice", "_version", "99"), Response::Error(ErrorCode::FieldNotAllowed, _)));
+    assert!(matches!(api::process_request("SETM users alice name Alice _version 99", None), Response::Error(ErrorCode::FieldNotAllowed, _)));
+    
+    assert_eq!(version("alice"), before);
+}
+
+#[test]
+fn init_in_replace_mode_carries_the_version_on() {
+    set_up();
+    common::ok(tree::handle_init("users", "bob", &[], None));
+    common::ok(tree::handle_set("users", "bob", "name", "Bob"));
+    let before = version("bob");
+    
+    common::ok(tree::handle_init("users", "bob", &[], None));
+    
+    assert_eq!(version("bob"), before + 1);
+    assert_eq!(common::module("users", "bob")["name"], "");
+}
+
+#[test]
+fn list_leaves_the_version_out_unless_asked() {
+    set_up();
+    common::ok(tree::handle_init("users", "carol", &[], None));
+    
+    let keys = |request: &str| match api::process_request(request, None) {
+        Response::List(keys) => keys,
+        other => panic!("{} returned {}", request, other.to_wire()),
+    };
+    
+    assert!(!keys("LIST users carol").contains(&"_version".to_string()));
+    assert!(keys("LIST users carol WITHVERSION").contains(&"_version".to_string()));
+    assert!(keys("LIST users carol").contains(&"name".to_string()));
+}